@@ -1,9 +1,273 @@
-use rocket::{config::Config as RocketConfig, Build, Rocket};
+use anyhow::Result;
+use rocket::config::{Config as RocketConfig, LogLevel, TlsConfig};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Method, Status};
+use rocket::route::{Handler, Outcome};
+use rocket::{Build, Data, Orbit, Request, Response, Rocket, Route};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BlackholeConfig {
+    pub port: u16,
+    // status returned for every blackholed request
+    pub status: u16,
+    pub delay_ms: u64,
+    pub rules: Vec<BlackholeRule>,
+    // set by serve rather than the config file, for the PAC file
+    #[serde(skip)]
+    pub https_port: Option<BoundPort>,
+    // set by serve from --log-level, like harbinger's own rocket
+    #[serde(skip)]
+    pub log_level: LogLevel,
+}
+
+// the port the HTTPS blackhole is listening on, for the HTTP blackhole's
+// PAC file. with port 0 it's only known once bound, so this is attached to
+// the HTTPS blackhole to record its port at liftoff, and read when the PAC
+// is requested
+#[derive(Debug, Clone)]
+pub struct BoundPort(Arc<AtomicU16>);
+
+impl BoundPort {
+    pub fn new(port: u16) -> Self {
+        BoundPort(Arc::new(AtomicU16::new(port)))
+    }
+
+    pub fn get(&self) -> u16 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for BoundPort {
+    fn info(&self) -> Info {
+        Info {
+            name: "Bound Port",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        self.0.store(rocket.config().port, Ordering::Relaxed);
+    }
+}
+
+// overrides for requests to a domain or any of its subdomains
+#[derive(Deserialize, Debug, Clone)]
+pub struct BlackholeRule {
+    pub domain: String,
+    pub status: Option<u16>,
+    pub delay_ms: Option<u64>,
+}
+
+impl Default for BlackholeConfig {
+    fn default() -> Self {
+        BlackholeConfig {
+            port: 8001,
+            status: 404,
+            delay_ms: 0,
+            rules: Vec::new(),
+            https_port: None,
+            log_level: LogLevel::Debug,
+        }
+    }
+}
+
+impl BlackholeConfig {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    // a PAC file sending everything but local requests to the blackhole
+    // listening on `port`, over HTTPS for https:// URLs if the HTTPS
+    // blackhole is running
+    fn proxy_pac(&self, port: u16) -> String {
+        let https_proxy = match &self.https_port {
+            Some(https_port) => format!("HTTPS 127.0.0.1:{}", https_port.get()),
+            None => format!("PROXY 127.0.0.1:{}", port),
+        };
+        include_str!("../static/proxy.pac")
+            .replace("HARBINGER_TMPL_BLACKHOLE_PORT", &port.to_string())
+            .replace("HARBINGER_TMPL_HTTPS_PROXY", &https_proxy)
+    }
+
+    fn rule_for(&self, domain: &str) -> Option<&BlackholeRule> {
+        self.rules.iter().find(|rule| {
+            domain.eq_ignore_ascii_case(&rule.domain)
+                || domain
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", rule.domain.to_ascii_lowercase()))
+        })
+    }
+}
+
+#[derive(Clone)]
+struct BlackholeHandler {
+    config: BlackholeConfig,
+}
+
+#[rocket::async_trait]
+impl Handler for BlackholeHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, _: Data<'r>) -> Outcome<'r> {
+        let rule = req
+            .host()
+            .and_then(|host| self.config.rule_for(host.domain().as_str()));
+        let status = rule
+            .and_then(|rule| rule.status)
+            .unwrap_or(self.config.status);
+        let delay_ms = rule
+            .and_then(|rule| rule.delay_ms)
+            .unwrap_or(self.config.delay_ms);
+        if delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        }
+        let mut res = Response::new();
+        res.set_status(Status::new(status));
+        Outcome::Success(res)
+    }
+}
+
+// renders the PAC file per request, since either server may have been
+// started on port 0 and only know its port once running
+#[derive(Clone)]
+struct PacHandler {
+    config: BlackholeConfig,
+}
+
+#[rocket::async_trait]
+impl Handler for PacHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, _: Data<'r>) -> Outcome<'r> {
+        let content_type = ContentType::new("application", "x-ns-proxy-autoconfig");
+        let pac = self.config.proxy_pac(req.rocket().config().port);
+        Outcome::from(req, (content_type, pac))
+    }
+}
 
 pub fn build_blackhole(port: u16) -> Rocket<Build> {
-    let server_config = RocketConfig::figment()
-        .merge(("port", port))
-        .merge(("log_level", "debug"));
+    build_blackhole_from_config(&BlackholeConfig {
+        port,
+        ..Default::default()
+    })
+}
+
+// the same blackhole over HTTPS, so requests to https:// URLs get a response
+// rather than a failed handshake. the certificate is generated for
+// `*.invalid`, so browsers will still warn about it for any real host
+pub fn build_https_blackhole(port: u16) -> Rocket<Build> {
+    build_https_blackhole_from_config(&BlackholeConfig {
+        port,
+        ..Default::default()
+    })
+}
+
+pub fn build_https_blackhole_from_config(config: &BlackholeConfig) -> Rocket<Build> {
+    // only fails for invalid subject names, and this one is valid
+    let cert = rcgen::generate_simple_self_signed(vec!["*.invalid".to_string()])
+        .expect("failed to generate blackhole certificate");
+    let tls = TlsConfig::from_bytes(
+        cert.serialize_pem()
+            .expect("failed to serialize blackhole certificate")
+            .as_bytes(),
+        cert.serialize_private_key_pem().as_bytes(),
+    );
+    build(config, Some(tls))
+}
+
+// browsers are pointed at http://127.0.0.1:<port>/proxy.pac, so only the HTTP
+// blackhole serves the PAC file
+pub fn build_blackhole_from_config(config: &BlackholeConfig) -> Rocket<Build> {
+    let pac = PacHandler {
+        config: config.clone(),
+    };
+    build(config, None).mount("/", vec![Route::new(Method::Get, "/proxy.pac", pac)])
+}
+
+fn build(config: &BlackholeConfig, tls: Option<TlsConfig>) -> Rocket<Build> {
+    let mut server_config = RocketConfig::figment()
+        .merge(("port", config.port))
+        .merge(("log_level", config.log_level));
+    if let Some(tls) = tls {
+        server_config = server_config.merge(("tls", tls));
+    }
+
+    let mut routes = Vec::new();
+    use rocket::http::Method::*;
+    for method in &[Get, Put, Post, Delete, Options, Head, Trace, Connect, Patch] {
+        let handler = BlackholeHandler {
+            config: config.clone(),
+        };
+        routes.push(Route::new(*method, "/<any..>", handler));
+    }
+
+    rocket::custom(server_config).mount("/", routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::asynchronous::Client;
+
+    #[rocket::async_test]
+    async fn pac_points_at_the_blackhole() {
+        let config = BlackholeConfig {
+            port: 8001,
+            ..Default::default()
+        };
+        let client = Client::untracked(build_blackhole_from_config(&config))
+            .await
+            .unwrap();
+        let pac = client
+            .get("/proxy.pac")
+            .dispatch()
+            .await
+            .into_string()
+            .await
+            .unwrap();
+        assert!(!pac.contains("HARBINGER_TMPL"), "{}", pac);
+        // without the HTTPS blackhole, https:// URLs go to the HTTP one too
+        assert!(
+            pac.contains(r#"BLACKHOLE_HTTP_PROXY = "PROXY 127.0.0.1:8001""#),
+            "{}",
+            pac
+        );
+        assert!(
+            pac.contains(r#"BLACKHOLE_HTTPS_PROXY = "PROXY 127.0.0.1:8001""#),
+            "{}",
+            pac
+        );
+    }
+
+    #[rocket::async_test]
+    async fn pac_uses_bound_https_port() {
+        let https_port = BoundPort::new(0);
+        let config = BlackholeConfig {
+            port: 8001,
+            https_port: Some(https_port.clone()),
+            ..Default::default()
+        };
+        let client = Client::untracked(build_blackhole_from_config(&config))
+            .await
+            .unwrap();
+        // as the HTTPS blackhole's liftoff would, once bound to a random port
+        https_port.0.store(43210, Ordering::Relaxed);
 
-    rocket::custom(server_config)
+        let pac = client
+            .get("/proxy.pac")
+            .dispatch()
+            .await
+            .into_string()
+            .await
+            .unwrap();
+        assert!(
+            pac.contains(r#"BLACKHOLE_HTTPS_PROXY = "HTTPS 127.0.0.1:43210""#),
+            "{}",
+            pac
+        );
+    }
 }
@@ -0,0 +1,128 @@
+use anyhow::{bail, Result};
+use clap::{parser::ValueSource, ArgAction, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::Path;
+
+// loaded if --config isn't given and it exists in the working directory
+pub const DEFAULT_CONFIG_PATH: &str = "harbinger.toml";
+
+// options read from a TOML file, for any not given on the command line.
+// top-level keys are global options, and each table holds a subcommand's
+// options, e.g.
+//   quiet = true
+//
+//   [serve]
+//   port = 8080
+//   proxy_pattern = ["^/api/", "^/graphql/"]
+// keys are the options' long names, with either dashes or underscores.
+// positional arguments like the HAR path can only be given on the command
+// line
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct ConfigFile {
+    values: toml::Table,
+}
+
+impl ConfigFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // sets an option in a subcommand's table
+    pub fn set(&mut self, subcommand: &str, key: &str, value: impl Into<toml::Value>) {
+        let table = self
+            .values
+            .entry(subcommand)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let toml::Value::Table(table) = table {
+            table.insert(key.to_string(), value.into());
+        }
+    }
+
+    // `cli` with this file's options appended, leaving out any that
+    // `matches`, parsed from `cli`, shows were given on the command line.
+    // options for other subcommands are checked but not used
+    pub fn apply(
+        &self,
+        command: &Command,
+        matches: &ArgMatches,
+        mut cli: Vec<OsString>,
+    ) -> Result<Vec<OsString>> {
+        let given = |matches: &ArgMatches, id: &str| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        };
+        for (key, value) in &self.values {
+            let toml::Value::Table(options) = value else {
+                let (id, args) = option_args(command, key, value)?;
+                if !given(matches, &id) {
+                    cli.extend(args);
+                }
+                continue;
+            };
+            let Some(subcommand) = command.find_subcommand(key) else {
+                bail!("unknown command [{}]", key);
+            };
+            let sub_matches = matches
+                .subcommand()
+                .filter(|(name, _)| *name == key.as_str())
+                .map(|(_, sub_matches)| sub_matches);
+            for (key, value) in options {
+                let (id, args) = option_args(subcommand, key, value)?;
+                if let Some(sub_matches) = sub_matches {
+                    if !given(sub_matches, &id) {
+                        cli.extend(args);
+                    }
+                }
+            }
+        }
+        Ok(cli)
+    }
+}
+
+// the arg id for a config key, and the command line arguments its value
+// stands for. arrays give a repeatable option once per element
+fn option_args(
+    command: &Command,
+    key: &str,
+    value: &toml::Value,
+) -> Result<(String, Vec<OsString>)> {
+    let id = key.replace('-', "_");
+    let Some(arg) = command
+        .get_arguments()
+        .find(|arg| arg.get_id().as_str() == id)
+    else {
+        bail!("unknown option `{}` for {}", key, command.get_name());
+    };
+    let Some(long) = arg.get_long() else {
+        bail!("`{}` can only be given on the command line", key);
+    };
+    let values = match value {
+        toml::Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+    let mut args = Vec::new();
+    for value in values {
+        match value {
+            toml::Value::Boolean(set) if matches!(arg.get_action(), ArgAction::SetTrue) => {
+                if *set {
+                    args.push(format!("--{}", long).into());
+                }
+            }
+            // the `=` form keeps values that start with a dash from being
+            // read as options
+            toml::Value::String(s) => args.push(format!("--{}={}", long, s).into()),
+            toml::Value::Array(_) | toml::Value::Table(_) => {
+                bail!("`{}` can't be a nested array or table", key);
+            }
+            value => args.push(format!("--{}={}", long, value).into()),
+        }
+    }
+    Ok((id, args))
+}
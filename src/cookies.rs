@@ -0,0 +1,282 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize)]
+struct StoredCookie {
+    domain: String,
+    // jars saved before paths were tracked sent every cookie to the whole
+    // domain
+    #[serde(default = "root_path")]
+    path: String,
+    // cookies set without a Domain attribute only go back to the host that
+    // set them, rather than to its subdomains too
+    #[serde(default = "host_only")]
+    host_only: bool,
+    name: String,
+    value: String,
+}
+
+fn root_path() -> String {
+    "/".to_string()
+}
+
+fn host_only() -> bool {
+    true
+}
+
+impl StoredCookie {
+    fn matches(&self, host: &str, path: &str) -> bool {
+        let domain_matches = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(host, &self.domain)
+        };
+        domain_matches && path_matches(path, &self.path)
+    }
+}
+
+// a server-side stand-in for the browser's cookie store: cookies set by
+// served responses are remembered by domain and path, and sent along with
+// later requests they match. the jar is written back to disk by `save`,
+// which the server calls on shutdown
+pub struct CookieJar {
+    path: PathBuf,
+    cookies: Mutex<Vec<StoredCookie>>,
+}
+
+impl CookieJar {
+    // loads a previously saved jar, or starts an empty one if the file
+    // doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut cookies = Vec::new();
+        if path.try_exists()? {
+            cookies = serde_json::from_slice(&std::fs::read(path)?)?;
+        }
+        Ok(CookieJar {
+            path: path.to_path_buf(),
+            cookies: Mutex::new(cookies),
+        })
+    }
+
+    // records the cookie from a Set-Cookie header value, e.g.
+    // `session=abc; Path=/; HttpOnly`, sent in response to a request for
+    // `request_path` on `host`. a Max-Age of 0 or less removes the cookie
+    pub fn store(&self, host: &str, request_path: &str, set_cookie: &str) {
+        let mut parts = set_cookie.split(';');
+        let Some((name, value)) = parts.next().and_then(|pair| pair.split_once('=')) else {
+            return;
+        };
+        let host = host.to_ascii_lowercase();
+        let mut cookie = StoredCookie {
+            domain: host.clone(),
+            path: default_path(request_path),
+            host_only: true,
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        };
+        let mut expired = false;
+        for attr in parts {
+            let (key, value) = attr.split_once('=').unwrap_or((attr, ""));
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("max-age") {
+                expired = value.parse::<i64>().is_ok_and(|max_age| max_age <= 0);
+            } else if key.eq_ignore_ascii_case("domain") && !value.is_empty() {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                // browsers reject cookies for domains other than the setting
+                // host's own
+                if !domain_matches(&host, &domain) {
+                    return;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            } else if key.eq_ignore_ascii_case("path") && value.starts_with('/') {
+                cookie.path = value.to_string();
+            }
+        }
+
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|stored| {
+            (&stored.domain, &stored.path, &stored.name)
+                != (&cookie.domain, &cookie.path, &cookie.name)
+        });
+        if !expired {
+            cookies.push(cookie);
+        }
+    }
+
+    // the Cookie header value for a request for `path` on `host`, if any
+    // stored cookies match it. cookies with longer paths come first, as
+    // browsers send them
+    pub fn cookie_header(&self, host: &str, path: &str) -> Option<String> {
+        let host = host.to_ascii_lowercase();
+        let cookies = self.cookies.lock().unwrap();
+        let mut matching: Vec<&StoredCookie> = cookies
+            .iter()
+            .filter(|cookie| cookie.matches(&host, path))
+            .collect();
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        (!pairs.is_empty()).then(|| pairs.join("; "))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_vec_pretty(&*self.cookies.lock().unwrap())?;
+        std::fs::write(&self.path, json)?;
+        Ok(())
+    }
+}
+
+// whether `host` is `domain` or one of its subdomains. IP addresses only
+// match themselves
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.parse::<IpAddr>().is_err()
+            && host
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.ends_with('.')))
+}
+
+// whether a cookie scoped to `cookie_path` is sent with a request for
+// `request_path`: `/api` covers `/api` and `/api/users` but not `/apis`
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+// the path a cookie set without a Path attribute is scoped to: the request
+// path up to its last `/`
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(last_slash) if last_slash > 0 && request_path.starts_with('/') => {
+            request_path[..last_slash].to_string()
+        }
+        _ => root_path(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jar() -> (tempfile::TempDir, CookieJar) {
+        let dir = tempfile::tempdir().unwrap();
+        let jar = CookieJar::load(&dir.path().join("cookies.json")).unwrap();
+        (dir, jar)
+    }
+
+    #[test]
+    fn host_only_cookies_stay_on_their_host() {
+        let (_dir, jar) = jar();
+        jar.store("Cool.Website", "/", "session=abc; HttpOnly");
+        assert_eq!(
+            jar.cookie_header("cool.website", "/").as_deref(),
+            Some("session=abc")
+        );
+        assert_eq!(jar.cookie_header("www.cool.website", "/"), None);
+        assert_eq!(jar.cookie_header("other.website", "/"), None);
+    }
+
+    #[test]
+    fn domain_cookies_cover_subdomains() {
+        let (_dir, jar) = jar();
+        jar.store("www.cool.website", "/", "session=abc; Domain=.cool.website");
+        for host in ["cool.website", "www.cool.website", "api.cool.website"] {
+            assert_eq!(
+                jar.cookie_header(host, "/").as_deref(),
+                Some("session=abc"),
+                "{}",
+                host
+            );
+        }
+        assert_eq!(jar.cookie_header("notcool.website", "/"), None);
+    }
+
+    #[test]
+    fn rejects_foreign_domains() {
+        let (_dir, jar) = jar();
+        jar.store("cool.website", "/", "a=1; Domain=other.website");
+        jar.store("cool.website", "/", "b=2; Domain=www.cool.website");
+        jar.store("127.0.0.1", "/", "c=3; Domain=0.0.1");
+        assert_eq!(jar.cookie_header("other.website", "/"), None);
+        assert_eq!(jar.cookie_header("www.cool.website", "/"), None);
+        assert_eq!(jar.cookie_header("0.0.1", "/"), None);
+    }
+
+    #[test]
+    fn matches_paths() {
+        let (_dir, jar) = jar();
+        jar.store("cool.website", "/", "root=1; Path=/");
+        jar.store("cool.website", "/", "api=2; Path=/api");
+        // without a Path, the cookie is scoped to the request's directory
+        jar.store("cool.website", "/account/settings", "account=3");
+
+        let header = |path| jar.cookie_header("cool.website", path);
+        assert_eq!(header("/").as_deref(), Some("root=1"));
+        assert_eq!(header("/apis").as_deref(), Some("root=1"));
+        assert_eq!(header("/api").as_deref(), Some("api=2; root=1"));
+        assert_eq!(header("/api/users").as_deref(), Some("api=2; root=1"));
+        assert_eq!(
+            header("/account/profile").as_deref(),
+            Some("account=3; root=1")
+        );
+        assert_eq!(header("/accounts").as_deref(), Some("root=1"));
+    }
+
+    #[test]
+    fn replaces_and_expires_cookies() {
+        let (_dir, jar) = jar();
+        jar.store("cool.website", "/", "session=abc");
+        jar.store("cool.website", "/", "session=def; Path=/api");
+        jar.store("cool.website", "/", "session=ghi");
+        assert_eq!(
+            jar.cookie_header("cool.website", "/api").as_deref(),
+            Some("session=def; session=ghi")
+        );
+
+        jar.store("cool.website", "/", "session=; Max-Age=0");
+        jar.store("cool.website", "/", "session=; Path=/api; Max-Age=-1");
+        assert_eq!(jar.cookie_header("cool.website", "/api"), None);
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let (dir, jar) = jar();
+        jar.store(
+            "www.cool.website",
+            "/",
+            "session=abc; Domain=cool.website; Path=/app",
+        );
+        jar.save().unwrap();
+
+        let loaded = CookieJar::load(&dir.path().join("cookies.json")).unwrap();
+        assert_eq!(
+            loaded.cookie_header("api.cool.website", "/app").as_deref(),
+            Some("session=abc")
+        );
+        assert_eq!(loaded.cookie_header("api.cool.website", "/"), None);
+    }
+
+    #[test]
+    fn loads_jars_without_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cookies.json");
+        std::fs::write(
+            &path,
+            r#"[{"domain": "cool.website", "name": "session", "value": "abc"}]"#,
+        )
+        .unwrap();
+        let jar = CookieJar::load(&path).unwrap();
+        assert_eq!(
+            jar.cookie_header("cool.website", "/any/path").as_deref(),
+            Some("session=abc")
+        );
+        assert_eq!(jar.cookie_header("www.cool.website", "/"), None);
+    }
+}
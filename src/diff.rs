@@ -0,0 +1,286 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use similar::TextDiff;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use swc_core::common::EqIgnoreSpan;
+
+use crate::har::{Entry, Har};
+use crate::js::{parse_js, print_script};
+
+// how `diff` prints its report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum DiffFormat {
+    // a line per added, deleted or modified entry, followed by its changes
+    Text,
+    // the report as a single JSON object
+    Json,
+}
+
+// how a response body is compared, based on its content type
+#[derive(PartialEq, Eq, Debug)]
+enum BodyKind {
+    Json,
+    JavaScript,
+    Text,
+    Binary,
+}
+
+impl BodyKind {
+    fn of(content_type: Option<&str>) -> Self {
+        match content_type {
+            Some(ct) if ct == "application/json" || ct.ends_with("+json") => BodyKind::Json,
+            Some("application/javascript" | "text/javascript") => BodyKind::JavaScript,
+            Some(ct) if ct.starts_with("text/") => BodyKind::Text,
+            _ => BodyKind::Binary,
+        }
+    }
+}
+
+fn text_diff(base: &str, updated: &str, context: usize) -> String {
+    TextDiff::from_lines(base, updated)
+        .unified_diff()
+        .context_radius(context)
+        .header("base", "updated")
+        .to_string()
+}
+
+// collects `path: old → new` lines for every leaf value that differs
+fn json_changes(
+    path: &str,
+    base: Option<&Value>,
+    updated: Option<&Value>,
+    changes: &mut Vec<String>,
+) {
+    match (base, updated) {
+        (Some(Value::Object(base)), Some(Value::Object(updated))) => {
+            let keys: BTreeSet<&String> = base.keys().chain(updated.keys()).collect();
+            for key in keys {
+                let path = format!("{}.{}", path, key);
+                json_changes(&path, base.get(key), updated.get(key), changes);
+            }
+        }
+        (Some(Value::Array(base)), Some(Value::Array(updated))) => {
+            for i in 0..base.len().max(updated.len()) {
+                let path = format!("{}[{}]", path, i);
+                json_changes(&path, base.get(i), updated.get(i), changes);
+            }
+        }
+        (base, updated) if base != updated => {
+            let show =
+                |value: Option<&Value>| value.map_or("(absent)".to_string(), Value::to_string);
+            changes.push(format!("{}: {} → {}", path, show(base), show(updated)));
+        }
+        _ => {}
+    }
+}
+
+// compares scripts by their syntax trees, so that changes to formatting
+// alone don't count. scripts that differ are diffed as printed by swc, which
+// lays both out the same way. None if either doesn't parse
+fn js_diff(base: &str, updated: &str, context: usize) -> Option<String> {
+    let base = parse_js("base".to_string(), base.to_string()).ok()?;
+    let updated = parse_js("updated".to_string(), updated.to_string()).ok()?;
+    if base.eq_ignore_span(&updated) {
+        return Some("syntax trees are identical, only formatting changed\n".to_string());
+    }
+    Some(text_diff(
+        &print_script(&base),
+        &print_script(&updated),
+        context,
+    ))
+}
+
+// a content-aware report of how a response body changed, showing `context`
+// unchanged lines around each change in text bodies
+pub fn body_diff(
+    content_type: Option<&str>,
+    base: &[u8],
+    updated: &[u8],
+    context: usize,
+) -> String {
+    let kind = BodyKind::of(content_type);
+    if kind == BodyKind::Json {
+        if let (Ok(base), Ok(updated)) = (
+            serde_json::from_slice::<Value>(base),
+            serde_json::from_slice::<Value>(updated),
+        ) {
+            let mut changes = Vec::new();
+            json_changes("response", Some(&base), Some(&updated), &mut changes);
+            return changes.join("\n") + "\n";
+        }
+    }
+    let size_change = format!("size: {} → {} bytes\n", base.len(), updated.len());
+    match (
+        kind,
+        std::str::from_utf8(base),
+        std::str::from_utf8(updated),
+    ) {
+        (BodyKind::Binary, _, _) => size_change,
+        (BodyKind::JavaScript, Ok(base), Ok(updated)) => {
+            js_diff(base, updated, context).unwrap_or_else(|| text_diff(base, updated, context))
+        }
+        (_, Ok(base), Ok(updated)) => text_diff(base, updated, context),
+        _ => size_change,
+    }
+}
+
+#[derive(Serialize)]
+struct EntryKey {
+    method: String,
+    url: String,
+}
+
+impl From<&Entry> for EntryKey {
+    fn from(entry: &Entry) -> Self {
+        EntryKey {
+            method: entry
+                .method()
+                .map_or("???".to_string(), |method| method.to_string()),
+            url: entry.url().to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HeaderChange {
+    name: String,
+    base: Option<String>,
+    updated: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ModifiedEntry {
+    #[serde(flatten)]
+    key: EntryKey,
+    // [base, updated], if the status changed
+    status: Option<[i64; 2]>,
+    headers: Vec<HeaderChange>,
+    body: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct HarDiff {
+    deleted: Vec<EntryKey>,
+    added: Vec<EntryKey>,
+    modified: Vec<ModifiedEntry>,
+}
+
+// response headers by lowercased name, with repeated headers joined
+fn header_map(entry: &Entry) -> BTreeMap<String, String> {
+    let mut headers: BTreeMap<String, String> = BTreeMap::new();
+    for (name, value) in entry.res_headers() {
+        headers
+            .entry(name.to_ascii_lowercase())
+            .and_modify(|values| {
+                values.push_str(", ");
+                values.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    headers
+}
+
+fn header_changes(base: &Entry, updated: &Entry) -> Vec<HeaderChange> {
+    let base = header_map(base);
+    let updated = header_map(updated);
+    let names: BTreeSet<&String> = base.keys().chain(updated.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| base.get(*name) != updated.get(*name))
+        .map(|name| HeaderChange {
+            name: name.clone(),
+            base: base.get(name).cloned(),
+            updated: updated.get(name).cloned(),
+        })
+        .collect()
+}
+
+impl HarDiff {
+    // entries are matched by method and URL. when several share them, the
+    // first in one HAR is compared with the first in the other, and so on
+    pub fn new(base: &Har, updated: &Har, context: usize) -> Self {
+        let key = |entry: &Entry| (entry.method().ok(), entry.url().to_string());
+        let mut updated_entries: HashMap<_, Vec<&Entry>> = HashMap::new();
+        for entry in updated.entries.iter().rev() {
+            updated_entries.entry(key(entry)).or_default().push(entry);
+        }
+        let mut diff = HarDiff::default();
+        for entry in &base.entries {
+            let Some(updated_entry) = updated_entries
+                .get_mut(&key(entry))
+                .and_then(|entries| entries.pop())
+            else {
+                diff.deleted.push(EntryKey::from(entry));
+                continue;
+            };
+            let base_body = entry.res_body().unwrap_or_default();
+            let updated_body = updated_entry.res_body().unwrap_or_default();
+            let content_type = updated_entry.content_type().or(entry.content_type());
+            let modified = ModifiedEntry {
+                key: EntryKey::from(entry),
+                status: (entry.status() != updated_entry.status())
+                    .then(|| [entry.status(), updated_entry.status()]),
+                headers: header_changes(entry, updated_entry),
+                body: (base_body != updated_body)
+                    .then(|| body_diff(content_type, &base_body, &updated_body, context)),
+            };
+            if modified.status.is_some() || !modified.headers.is_empty() || modified.body.is_some()
+            {
+                diff.modified.push(modified);
+            }
+        }
+        let unmatched: BTreeSet<usize> = updated_entries
+            .values()
+            .flatten()
+            .map(|entry| entry.index())
+            .collect();
+        diff.added = updated
+            .entries
+            .iter()
+            .filter(|entry| unmatched.contains(&entry.index()))
+            .map(EntryKey::from)
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deleted.is_empty() && self.added.is_empty() && self.modified.is_empty()
+    }
+
+    pub fn print(&self, format: DiffFormat) -> Result<()> {
+        if format == DiffFormat::Json {
+            println!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        }
+        if self.is_empty() {
+            println!("no differences");
+        }
+        for key in &self.deleted {
+            println!("deleted {} {}", key.method, key.url);
+        }
+        for key in &self.added {
+            println!("added {} {}", key.method, key.url);
+        }
+        for entry in &self.modified {
+            println!("modified {} {}", entry.key.method, entry.key.url);
+            if let Some([base, updated]) = entry.status {
+                println!("status: {} → {}", base, updated);
+            }
+            let show = |value: &Option<String>| value.clone().unwrap_or("(absent)".to_string());
+            for header in &entry.headers {
+                println!(
+                    "header {}: {} → {}",
+                    header.name,
+                    show(&header.base),
+                    show(&header.updated)
+                );
+            }
+            if let Some(body) = &entry.body {
+                print!("{}", body);
+            }
+        }
+        Ok(())
+    }
+}
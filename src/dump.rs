@@ -1,71 +1,781 @@
 use anyhow::Result;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::fs::{create_dir, create_dir_all, OpenOptions};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{create_dir_all, File, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task;
+use tracing::info_span;
+use url::Url;
 
 use crate::error::HarbingerError;
-use crate::har::Har;
-use crate::js::{parse_js, unpack_webpack_chunk_list, write_script};
+use crate::har::{Entry, EntrySort, Har};
+use crate::html::unminify_html;
+use crate::js::{
+    extract_chunk_names, parse_js, print_script, resolve_chunk_name, unpack_webpack_chunk_list,
+    write_script, ChunkNames,
+};
 
-pub fn dump(har: &Har, output_path: &PathBuf, raw: bool) -> Result<()> {
-    if output_path.try_exists()? {
-        return Err(HarbingerError::DumpPathExists.into());
+#[derive(Clone, Default)]
+pub struct DumpOptions {
+    pub raw: bool,
+    // methods to dump, or all methods if None
+    pub include_methods: Option<Vec<String>>,
+    pub exclude_methods: Vec<String>,
+    // number of entries to skip before dumping
+    pub entry_offset: usize,
+    // maximum number of entries to dump, or all remaining entries if None
+    pub entry_limit: Option<usize>,
+    // entries processed at once, or one per core if 0
+    pub jobs: usize,
+    // skip everything but javascript, including source maps
+    pub only_javascript: bool,
+    // write every entry directly into the output directory
+    pub flatten: bool,
+    // continue an interrupted dump, skipping files the manifest says are done
+    pub resume: bool,
+    // when resuming, rewrite every file anyway
+    pub force: bool,
+    // hide the progress bar, printing each entry's status as a plain line
+    pub no_progress: bool,
+    // the order entries are dumped in, or recording order if None. the
+    // offset and limit apply to the sorted entries
+    pub entry_sort: Option<EntrySort>,
+    // stop before starting on another entry once this has passed
+    pub deadline: Option<Instant>,
+    // dump into an existing output directory, skipping files that already
+    // exist there
+    pub incremental: bool,
+    // when dumping incrementally, rewrite files that already exist
+    pub overwrite: bool,
+    // reformat JSON responses with indentation
+    pub pretty_json: bool,
+    // reformat CSS responses with indentation
+    pub unminify_css: bool,
+    // reformat HTML responses, including their inline javascript
+    pub unminify_html: bool,
+}
+
+// why an entry's file isn't being written
+enum Skip {
+    // the manifest says a previous run finished writing it
+    Cached,
+    // it already exists, and this is an incremental dump
+    Exists,
+}
+
+// an entry that couldn't be dumped
+pub struct DumpFailure {
+    pub url: String,
+    pub error: anyhow::Error,
+}
+
+// how many files a dump has skipped and written so far, and which entries
+// failed
+#[derive(Default)]
+pub struct DumpSummary {
+    pub cached: usize,
+    pub existing: usize,
+    pub written: usize,
+    pub failures: Vec<DumpFailure>,
+}
+
+impl fmt::Display for DumpSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} skipped (cached), ", self.cached)?;
+        if self.existing > 0 {
+            write!(f, "{} skipped (exists), ", self.existing)?;
+        }
+        write!(f, "{} written", self.written)?;
+        if !self.failures.is_empty() {
+            write!(f, ", {} failed", self.failures.len())?;
+        }
+        Ok(())
     }
-    create_dir(output_path)?;
+}
 
-    let pb_style =
-        ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {bar} {msg}").unwrap();
-    let pb = ProgressBar::new(har.entries.len() as u64);
-    pb.set_style(pb_style);
+const MANIFEST_FILE_NAME: &str = "_manifest.json";
+// a line is appended for each file as it's written, and folded into the
+// manifest when the dump stops. one left behind by a dump that was killed
+// is picked up by the next
+const MANIFEST_JOURNAL_FILE_NAME: &str = "_manifest.journal";
+
+// records which URL each dumped file came from, and how large it was when
+// written so that --resume can tell finished files from partial ones
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    // relative to the output directory
+    file: String,
+    url: String,
+    sha256: String,
+    size: u64,
+}
+
+fn read_manifest(output_path: &Path) -> Result<BTreeMap<String, ManifestEntry>> {
+    let mut manifest = BTreeMap::new();
+    let manifest_path = output_path.join(MANIFEST_FILE_NAME);
+    if manifest_path.try_exists()? {
+        let entries: Vec<ManifestEntry> = serde_json::from_slice(&std::fs::read(manifest_path)?)?;
+        for entry in entries {
+            manifest.insert(entry.file.clone(), entry);
+        }
+    }
+    let journal_path = output_path.join(MANIFEST_JOURNAL_FILE_NAME);
+    if journal_path.try_exists()? {
+        for line in std::fs::read_to_string(journal_path)?.lines() {
+            // the last line may have been cut short
+            if let Ok(entry) = serde_json::from_str::<ManifestEntry>(line) {
+                manifest.insert(entry.file.clone(), entry);
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+fn write_manifest(output_path: &Path, manifest: &BTreeMap<String, ManifestEntry>) -> Result<()> {
+    let entries: Vec<&ManifestEntry> = manifest.values().collect();
+    std::fs::write(
+        output_path.join(MANIFEST_FILE_NAME),
+        serde_json::to_vec_pretty(&entries)?,
+    )?;
+    Ok(())
+}
 
-    for (i, entry) in har.entries.iter().enumerate() {
-        let uri = entry.uri()?;
-        pb.set_prefix(format!("[{}/{}]", i, har.entries.len()));
-        pb.set_message(format!("processing {}", uri));
+// the manifest key for a file in the dump
+fn manifest_key(output_path: &Path, path: &Path) -> String {
+    path.strip_prefix(output_path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+// types whose bodies are always written byte for byte, never reformatted
+const BINARY_CONTENT_TYPE_PREFIXES: &[&str] = &[
+    "image/",
+    "font/",
+    "audio/",
+    "video/",
+    "application/wasm",
+    "application/zip",
+    "application/gzip",
+    "application/pdf",
+    "application/octet-stream",
+    "application/font-",
+    "application/x-font-",
+];
+
+fn is_binary_content_type(ct: &str) -> bool {
+    BINARY_CONTENT_TYPE_PREFIXES
+        .iter()
+        .any(|prefix| ct.starts_with(prefix))
+}
+
+fn is_binary(entry: &Entry) -> bool {
+    entry.content_type().is_some_and(is_binary_content_type)
+}
+
+impl DumpOptions {
+    fn should_dump(&self, entry: &Entry) -> Result<bool> {
+        if self.only_javascript && (!entry.is_javascript() || entry.is_source_map()) {
+            return Ok(false);
+        }
+        let method = entry.method()?;
+        let matches = |m: &String| m.eq_ignore_ascii_case(method.as_str());
+        if let Some(include_methods) = &self.include_methods {
+            if !include_methods.iter().any(matches) {
+                return Ok(false);
+            }
+        }
+        Ok(!self.exclude_methods.iter().any(matches))
+    }
 
-        let path = entry.get_dump_path(output_path)?;
-        if let Some(parent_path) = path.parent() {
-            create_dir_all(parent_path)?;
+    // how to reformat a (non-javascript) entry's body before writing it, and
+    // the name of the format, if at all
+    fn formatter(&self, entry: &Entry) -> Option<(&'static str, Formatter)> {
+        if self.raw || is_binary(entry) {
+            return None;
         }
+        match entry.content_type()? {
+            ct if self.pretty_json && (ct == "application/json" || ct.ends_with("+json")) => {
+                Some(("json", pretty_print_json))
+            }
+            "text/css" if self.unminify_css => Some(("css", unminify_css)),
+            "text/html" if self.unminify_html => Some(("html", unminify_html)),
+            _ => None,
+        }
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn should_unminify(&self, entry: &Entry) -> Result<bool> {
+        Ok(!self.raw
+            && !entry.is_data_uri()
+            && !is_binary(entry)
+            && entry.is_javascript()
+            && self.should_dump(entry)?)
+    }
+}
+
+// reformats a response body to be readable
+type Formatter = fn(&[u8]) -> Result<Vec<u8>>;
+
+// keys keep their original order, since serde_json preserves it
+fn pretty_print_json(body: &[u8]) -> Result<Vec<u8>> {
+    let value: serde_json::Value = serde_json::from_slice(body)?;
+    let mut pretty = Vec::new();
+    serde_json::to_writer_pretty(&mut pretty, &value)?;
+    pretty.push(b'\n');
+    Ok(pretty)
+}
+
+// CSS is valid SCSS, so a Sass compiler can re-indent it
+fn unminify_css(body: &[u8]) -> Result<Vec<u8>> {
+    let css = std::str::from_utf8(body)?;
+    let options = grass::Options::default().style(grass::OutputStyle::Expanded);
+    let expanded = grass::from_string(css, &options).map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(expanded.into_bytes())
+}
+
+// the URL in a `//# sourceMappingURL=` comment on the script's last line
+fn source_mapping_url(body: &str) -> Option<&str> {
+    let last_line = body.trim_end().lines().last()?.trim();
+    last_line
+        .strip_prefix("//# sourceMappingURL=")
+        .or_else(|| last_line.strip_prefix("//@ sourceMappingURL="))
+        .map(str::trim)
+}
+
+// where a script's source map comes from
+enum MapSource {
+    // inlined as a data: URI
+    Inline(Vec<u8>),
+    // another entry in the HAR
+    Entry(Arc<Entry>),
+}
 
-        pb.println(format!("processing {}", uri));
-        let body_bytes = match entry.res_body() {
-            Some(bytes) => bytes,
-            None => continue,
+fn find_source_map(
+    entry: &Entry,
+    map_url: &str,
+    entries_by_url: &HashMap<String, Arc<Entry>>,
+) -> Result<Option<MapSource>> {
+    if let Some(data) = map_url.strip_prefix("data:") {
+        let Some((media_type, payload)) = data.split_once(',') else {
+            return Ok(None);
         };
-        if !raw && entry.res_header("content-type") == Some("application/javascript") {
-            pb.println(" * parsing...");
-            let body_str = std::str::from_utf8(&body_bytes).unwrap();
-            let script = parse_js(path.to_string_lossy().to_string(), body_str.to_string())?;
-            if let Some(chunks) = unpack_webpack_chunk_list(&script) {
-                let mut unpack_path = path.with_extension("");
-                let file_name = unpack_path.file_name().unwrap().to_str().unwrap();
-                unpack_path.set_file_name(format!("{}_unbundled", file_name));
-                pb.println(format!(
-                    " * detected {} webpack chunks, unpacking to {}...",
-                    chunks.len(),
-                    unpack_path.display()
-                ));
-                create_dir(&unpack_path)?;
-                for chunk in chunks {
-                    pb.println(format!("  * unpacking {}...", chunk.label));
-                    let mut chunk_path = unpack_path.join(&chunk.label);
-                    chunk_path.set_extension("js");
-                    write_script(&chunk.into_script(), &chunk_path)?;
+        if media_type.ends_with(";base64") {
+            return Ok(Some(MapSource::Inline(STANDARD.decode(payload)?)));
+        }
+        return Ok(Some(MapSource::Inline(payload.as_bytes().to_vec())));
+    }
+    let resolved = Url::parse(entry.url())?.join(map_url)?;
+    Ok(entries_by_url
+        .get(resolved.as_str())
+        .map(|map_entry| MapSource::Entry(map_entry.clone())))
+}
+
+// resolves collisions between flattened file names by appending `_2`, `_3`...
+fn unique_flat_name(name: String, used: &mut HashSet<String>) -> String {
+    if used.insert(name.clone()) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{}_{}", name, n))
+        .find(|candidate| used.insert(candidate.clone()))
+        .unwrap()
+}
+
+// the progress bar swallows println when it's hidden, so status lines are
+// printed directly instead
+fn status(pb: &ProgressBar, msg: impl AsRef<str>) {
+    if pb.is_hidden() {
+        println!("{}", msg.as_ref());
+    } else {
+        pb.println(msg);
+    }
+}
+
+// everything the tasks writing entries share
+struct DumpContext {
+    output_path: PathBuf,
+    options: DumpOptions,
+    // for finding source maps, keyed by normalized URL
+    entries_by_url: HashMap<String, Arc<Entry>>,
+    // where each entry in this dump goes, keyed by normalized URL
+    dump_paths: HashMap<String, PathBuf>,
+    // gathered from every script as it's parsed
+    chunk_names: Mutex<ChunkNames>,
+    // the directory and label of each webpack chunk unpacked so far
+    unpacked_chunks: Mutex<Vec<(PathBuf, String)>>,
+    manifest: Mutex<BTreeMap<String, ManifestEntry>>,
+    journal: Mutex<File>,
+    summary: Mutex<DumpSummary>,
+    pb: ProgressBar,
+    // set by the first task to notice the deadline has passed
+    timed_out: AtomicBool,
+}
+
+// an entry waiting to be written: its index into the dumped entries, and
+// where it goes
+type PendingEntry = (usize, Arc<Entry>, PathBuf);
+
+// where a webpack chunk is unpacked to, named after its label
+fn chunk_path(unpack_path: &Path, label: &str) -> PathBuf {
+    let mut chunk_path = unpack_path.join(label);
+    chunk_path.set_extension("js");
+    chunk_path
+}
+
+// writes an entry's file, along with any webpack chunks and source map it
+// has. status lines are collected in `log` so they can be printed together,
+// rather than interleaved with other tasks'
+fn write_entry(ctx: &DumpContext, entry: &Entry, path: &Path, log: &mut Vec<String>) -> Result<()> {
+    if let Some(parent_path) = path.parent() {
+        create_dir_all(parent_path)?;
+    }
+
+    log.push(format!("processing {}", entry));
+    let Some(body_bytes) = entry.res_body() else {
+        return Ok(());
+    };
+    let script_text = if entry.is_javascript() && !is_binary(entry) {
+        std::str::from_utf8(&body_bytes).ok()
+    } else {
+        None
+    };
+    let map_url = script_text.and_then(source_mapping_url);
+    let mut map_path = path.to_path_buf().into_os_string();
+    map_path.push(".map");
+    let map_path = PathBuf::from(map_path);
+    if ctx.options.should_unminify(entry)? {
+        log.push(" * parsing...".to_string());
+        if let Some(charset) = entry.response_charset() {
+            if !charset.eq_ignore_ascii_case("utf-8") {
+                log.push(format!(" * warning: parsing {} body as utf-8", charset));
+            }
+        }
+        let script = {
+            let _span = info_span!("parse_js").entered();
+            let text = std::str::from_utf8(&body_bytes)?;
+            parse_js(path.to_string_lossy().to_string(), text.to_string())?
+        };
+        ctx.chunk_names
+            .lock()
+            .unwrap()
+            .extend(extract_chunk_names(&script));
+        if let Some(chunks) = unpack_webpack_chunk_list(&script) {
+            let mut unpack_path = path.with_extension("");
+            let file_name = unpack_path.file_name().unwrap().to_str().unwrap();
+            unpack_path.set_file_name(format!("{}_unbundled", file_name));
+            log.push(format!(
+                " * detected {} webpack chunks, unpacking to {}...",
+                chunks.len(),
+                unpack_path.display()
+            ));
+            create_dir_all(&unpack_path)?;
+            for chunk in chunks {
+                log.push(format!("  * unpacking {}...", chunk.label));
+                let label = chunk.label.clone();
+                let chunk_path = chunk_path(&unpack_path, &label);
+                let _span = info_span!("write_file", path = %chunk_path.display()).entered();
+                write_script(&chunk.into_script(), &chunk_path)?;
+                ctx.unpacked_chunks
+                    .lock()
+                    .unwrap()
+                    .push((unpack_path.clone(), label));
+            }
+        }
+        log.push(" * unminifying...".to_string());
+        let mut code = print_script(&script);
+        // printing drops comments, so the script is pointed back at its
+        // source map: an inline one at the .map file it's written out to,
+        // and any other at the URL it was recorded with
+        if let Some(map_url) = map_url {
+            let map_link = if map_url.starts_with("data:") {
+                map_path.file_name().unwrap().to_string_lossy().to_string()
+            } else {
+                map_url.to_string()
+            };
+            if !code.ends_with('\n') {
+                code.push('\n');
+            }
+            code.push_str(&format!("//# sourceMappingURL={}\n", map_link));
+        }
+        let _span = info_span!("write_file", path = %path.display()).entered();
+        std::fs::write(path, code)?;
+    } else {
+        let formatted = match ctx.options.formatter(entry) {
+            Some((name, format)) => {
+                log.push(format!(" * formatting {}...", name));
+                match format(&body_bytes) {
+                    Ok(formatted) => Some(formatted),
+                    Err(e) => {
+                        log.push(format!(
+                            " * warning: failed to parse {}, writing as-is: {}",
+                            name, e
+                        ));
+                        None
+                    }
                 }
             }
-            pb.println(" * unminifying...");
-            write_script(&script, &path)?;
+            None => {
+                log.push(" * writing normally...".to_string());
+                None
+            }
+        };
+        let _span = info_span!("write_file", path = %path.display()).entered();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(formatted.as_deref().unwrap_or(&body_bytes))?;
+    }
+    let key = manifest_key(&ctx.output_path, path);
+    let written = ManifestEntry {
+        file: key.clone(),
+        url: entry.url().to_string(),
+        sha256: format!("{:x}", Sha256::digest(&body_bytes)),
+        size: path.metadata()?.len(),
+    };
+    // journaled as we go, so an interrupted dump can be resumed
+    let mut line = serde_json::to_vec(&written)?;
+    line.push(b'\n');
+    ctx.journal.lock().unwrap().write_all(&line)?;
+    ctx.manifest.lock().unwrap().insert(key, written);
+    ctx.summary.lock().unwrap().written += 1;
+    if let Some(map_url) = map_url {
+        match find_source_map(entry, map_url, &ctx.entries_by_url) {
+            Ok(Some(MapSource::Inline(source_map))) => {
+                // inline maps are encoded onto a single line, so they're
+                // reformatted to be readable
+                let source_map = pretty_print_json(&source_map).unwrap_or(source_map);
+                log.push(format!(
+                    " * writing source map to {}...",
+                    map_path.display()
+                ));
+                let _span = info_span!("write_file", path = %map_path.display()).entered();
+                std::fs::write(map_path, source_map)?;
+            }
+            Ok(Some(MapSource::Entry(map_entry))) => {
+                write_map_entry(ctx, &map_entry, log)?;
+            }
+            Ok(None) => log.push(format!(" * source map {} not found", map_url)),
+            Err(e) => log.push(format!(" * failed to read source map: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+// writes an external source map to its own dump path, unless it's one of
+// the entries this dump writes anyway
+fn write_map_entry(ctx: &DumpContext, map_entry: &Entry, log: &mut Vec<String>) -> Result<()> {
+    let key = Url::parse(map_entry.url())?.to_string();
+    if let Some(map_path) = ctx.dump_paths.get(&key) {
+        log.push(format!(" * source map is dumped to {}", map_path.display()));
+        return Ok(());
+    }
+    let map_path = if ctx.options.flatten {
+        ctx.output_path.join(map_entry.get_flat_dump_name())
+    } else {
+        map_entry.get_dump_path(&ctx.output_path)?
+    };
+    let Some(source_map) = map_entry.res_body() else {
+        log.push(format!(" * source map {} has no body", map_entry.url()));
+        return Ok(());
+    };
+    if let Some(parent_path) = map_path.parent() {
+        create_dir_all(parent_path)?;
+    }
+    log.push(format!(
+        " * writing source map to {}...",
+        map_path.display()
+    ));
+    let _span = info_span!("write_file", path = %map_path.display()).entered();
+    std::fs::write(map_path, source_map)?;
+    Ok(())
+}
+
+// writes entries that share an output path, in order, so that the last one
+// wins just as it would in a sequential dump
+fn write_entries(ctx: &DumpContext, pending: Vec<PendingEntry>) {
+    for (i, entry, path) in pending {
+        if ctx.options.past_deadline() {
+            ctx.timed_out.store(true, Ordering::Relaxed);
+            return;
+        }
+        let _entry_span = info_span!("dump_entry", url = %entry.url(), index = i).entered();
+        ctx.pb.set_prefix(format!(
+            "[{}/{}]",
+            ctx.pb.position(),
+            ctx.pb.length().unwrap_or(0)
+        ));
+        let counts = ctx.summary.lock().unwrap().to_string();
+        ctx.pb
+            .set_message(format!("{}, processing {}", counts, entry.url()));
+        let mut log = Vec::new();
+        if let Err(error) = write_entry(ctx, &entry, &path, &mut log) {
+            log.push(format!(" * failed: {}", error));
+            ctx.summary.lock().unwrap().failures.push(DumpFailure {
+                url: entry.url().to_string(),
+                error,
+            });
+        }
+        status(&ctx.pb, log.join("\n"));
+        ctx.pb.inc(1);
+    }
+}
+
+// chunks are unpacked under their ids, and renamed once every script has
+// been parsed, since a webpack runtime can be recorded after the chunks it
+// names
+fn name_chunks(ctx: &DumpContext) {
+    let chunk_names = ctx.chunk_names.lock().unwrap();
+    for (unpack_path, label) in ctx.unpacked_chunks.lock().unwrap().drain(..) {
+        let name = resolve_chunk_name(&label, &chunk_names);
+        if name == label {
+            continue;
+        }
+        let (from, to) = (
+            chunk_path(&unpack_path, &label),
+            chunk_path(&unpack_path, &name),
+        );
+        if to.exists() {
+            status(
+                &ctx.pb,
+                format!("not naming {}, {} exists", from.display(), to.display()),
+            );
+            continue;
+        }
+        if let Err(e) = std::fs::rename(&from, &to) {
+            status(&ctx.pb, format!("failed to name {}: {}", from.display(), e));
+        }
+    }
+}
+
+// writes out the manifest, now that the journal isn't needed
+fn finish_manifest(ctx: &DumpContext) -> Result<()> {
+    write_manifest(&ctx.output_path, &ctx.manifest.lock().unwrap())?;
+    std::fs::remove_file(ctx.output_path.join(MANIFEST_JOURNAL_FILE_NAME))?;
+    Ok(())
+}
+
+// dumps entries concurrently, on up to `options.jobs` blocking tasks. an
+// entry that fails is recorded in the returned summary rather than stopping
+// the dump
+pub async fn dump(har: &Har, output_path: &PathBuf, options: &DumpOptions) -> Result<DumpSummary> {
+    if !options.resume && !options.incremental && output_path.try_exists()? {
+        return Err(HarbingerError::DumpPathExists.into());
+    }
+    create_dir_all(output_path)?;
+
+    let pb_style =
+        ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {bar} {msg}").unwrap();
+    // the tasks outlive this borrow of the HAR, so they share a copy of its
+    // entries
+    let har_entries: Vec<Arc<Entry>> = har.entries.iter().cloned().map(Arc::new).collect();
+    let mut sorted = har_entries.clone();
+    if let Some(entry_sort) = options.entry_sort {
+        entry_sort.sort(&mut sorted);
+    }
+    let entries: Vec<Arc<Entry>> = sorted
+        .into_iter()
+        .skip(options.entry_offset)
+        .take(options.entry_limit.unwrap_or(usize::MAX))
+        .collect();
+    // a bar redrawn in place only makes sense on a terminal, so it's also
+    // hidden when stdout is piped or captured
+    let pb = if options.no_progress || !console::Term::stdout().is_term() {
+        ProgressBar::with_draw_target(Some(entries.len() as u64), ProgressDrawTarget::hidden())
+    } else {
+        ProgressBar::new(entries.len() as u64)
+    };
+    pb.set_style(pb_style);
+
+    // where each entry is dumped to, or None if it's skipped. flattened names
+    // depend on the names that came before them, so they're all worked out
+    // up front
+    let mut flat_names = HashSet::new();
+    let mut paths = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        if entry.is_data_uri() || !options.should_dump(entry)? {
+            paths.push(None);
+        } else if options.flatten {
+            let name = unique_flat_name(entry.get_flat_dump_name(), &mut flat_names);
+            paths.push(Some(output_path.join(name)));
         } else {
-            pb.println(" * writing normally...");
-            let mut file = OpenOptions::new().write(true).create(true).open(&path)?;
-            file.write_all(&body_bytes)?;
+            paths.push(Some(entry.get_dump_path(output_path)?));
+        }
+    }
+
+    // files a previous run finished writing. incremental dumps keep the
+    // manifest so it still covers the files they skip
+    let mut manifest = read_manifest(output_path)?;
+    if !(options.resume || options.incremental) || options.force {
+        manifest.clear();
+    }
+    let is_cached = |path: &Path| {
+        manifest
+            .get(&manifest_key(output_path, path))
+            .is_some_and(|written| path.metadata().is_ok_and(|md| md.len() == written.size))
+    };
+    let skips: Vec<Option<Skip>> = paths
+        .iter()
+        .map(|path| match path {
+            Some(path) if is_cached(path) => Some(Skip::Cached),
+            Some(path) if options.incremental && !options.overwrite && path.exists() => {
+                Some(Skip::Exists)
+            }
+            _ => None,
+        })
+        .collect();
+    // the paths still to be written
+    let pending_paths: Vec<Option<PathBuf>> = paths
+        .iter()
+        .zip(&skips)
+        .map(|(path, skip)| path.clone().filter(|_| skip.is_none()))
+        .collect();
+
+    // skipped entries are counted up front, the rest as they're written
+    let mut summary = DumpSummary::default();
+    for (i, entry) in entries.iter().enumerate() {
+        match (&paths[i], &skips[i]) {
+            (None, _) if !entry.is_data_uri() => status(&pb, format!("skipping {}", entry)),
+            (None, _) => {}
+            (Some(_), Some(Skip::Cached)) => summary.cached += 1,
+            (Some(_), Some(Skip::Exists)) => summary.existing += 1,
+            (Some(_), None) => continue,
         }
         pb.inc(1);
     }
+
+    let jobs = match options.jobs {
+        0 => std::thread::available_parallelism()?.get(),
+        jobs => jobs,
+    };
+    let permits = Arc::new(Semaphore::new(jobs));
+
+    // the journal is about to be truncated, so whatever it held (along with
+    // any files forgotten above) is folded into the manifest first
+    write_manifest(output_path, &manifest)?;
+    let journal = File::create(output_path.join(MANIFEST_JOURNAL_FILE_NAME))?;
+
+    let ctx = Arc::new(DumpContext {
+        output_path: output_path.clone(),
+        options: options.clone(),
+        entries_by_url: har_entries
+            .iter()
+            .filter_map(|entry| Some((Url::parse(entry.url()).ok()?.to_string(), entry.clone())))
+            .collect(),
+        dump_paths: entries
+            .iter()
+            .zip(&paths)
+            .filter_map(|(entry, path)| {
+                Some((Url::parse(entry.url()).ok()?.to_string(), path.clone()?))
+            })
+            .collect(),
+        chunk_names: Mutex::new(ChunkNames::default()),
+        unpacked_chunks: Mutex::new(Vec::new()),
+        manifest: Mutex::new(manifest),
+        journal: Mutex::new(journal),
+        summary: Mutex::new(summary),
+        pb: pb.clone(),
+        timed_out: AtomicBool::new(false),
+    });
+
+    // entries with the same path (e.g. a URL requested twice) are written by
+    // the same task, so no two tasks ever touch the same file
+    let mut groups: Vec<Vec<PendingEntry>> = Vec::new();
+    let mut group_by_path: HashMap<&PathBuf, usize> = HashMap::new();
+    for (i, path) in pending_paths.iter().enumerate() {
+        let Some(path) = path else {
+            continue;
+        };
+        let group = *group_by_path.entry(path).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push((i, entries[i].clone(), path.clone()));
+    }
+    let mut tasks = Vec::with_capacity(groups.len());
+    for pending in groups {
+        let permit = permits.clone().acquire_owned().await?;
+        let ctx = ctx.clone();
+        tasks.push(task::spawn_blocking(move || {
+            let _permit = permit;
+            write_entries(&ctx, pending);
+        }));
+    }
+    for task in tasks {
+        task.await?;
+    }
+    name_chunks(&ctx);
+    finish_manifest(&ctx)?;
+
+    let summary = std::mem::take(&mut *ctx.summary.lock().unwrap());
+    if ctx.timed_out.load(Ordering::Relaxed) {
+        // everything written so far is in the manifest, so the dump can be
+        // picked up again with --resume
+        pb.abandon_with_message(format!("timed out! {}", summary));
+        return Err(HarbingerError::TimedOut.into());
+    }
     pb.inc(1);
-    pb.finish_with_message("finished!");
+    let message = format!("finished! {}", summary);
+    if pb.is_hidden() {
+        println!("{}", message);
+    }
+    pb.finish_with_message(message);
 
-    Ok(())
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest_line(file: &str, size: u64) -> String {
+        serde_json::to_string(&ManifestEntry {
+            file: file.to_string(),
+            url: format!("https://example.com/{}", file),
+            sha256: String::new(),
+            size,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn read_manifest_replays_the_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = BTreeMap::new();
+        for (file, size) in [("a.js", 1), ("b.js", 2)] {
+            let line = manifest_line(file, size);
+            let entry: ManifestEntry = serde_json::from_str(&line).unwrap();
+            manifest.insert(file.to_string(), entry);
+        }
+        write_manifest(dir.path(), &manifest).unwrap();
+        // a dump killed partway through writing its last line
+        let journal = format!(
+            "{}\n{}\n{{\"file\":\"d.js\",\"url\"",
+            manifest_line("b.js", 20),
+            manifest_line("c.js", 3)
+        );
+        std::fs::write(dir.path().join(MANIFEST_JOURNAL_FILE_NAME), journal).unwrap();
+
+        let manifest = read_manifest(dir.path()).unwrap();
+        let sizes: Vec<(&str, u64)> = manifest
+            .values()
+            .map(|entry| (entry.file.as_str(), entry.size))
+            .collect();
+        assert_eq!(sizes, [("a.js", 1), ("b.js", 20), ("c.js", 3)]);
+    }
 }
@@ -5,8 +5,6 @@ use thiserror::Error;
 pub enum HarbingerError {
     #[error("io error")]
     IoError(#[from] std::io::Error),
-    #[error("unsupported HAR version")]
-    UnsupportedHarVersion,
     #[error("HAR error")]
     HarError(#[from] HarError),
     #[error("dump path exists! cowardly bailing")]
@@ -15,4 +13,10 @@ pub enum HarbingerError {
     InvalidHarEntryUri { uri: String },
     #[error("Invalid HAR entry: invalid method {method}")]
     InvalidHarEntryMethod { method: String },
+    #[error("response body for {url} is {size} bytes, over the {limit} byte limit")]
+    ResponseTooLarge { url: String, size: u64, limit: u64 },
+    #[error("timed out")]
+    TimedOut,
+    #[error("no page with id {id} in HAR")]
+    UnknownPage { id: String },
 }
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use log::info;
+use std::path::PathBuf;
+
+use crate::har::Har;
+use crate::server::find_override;
+
+// replaces the response bodies of `har`'s entries with the files that would
+// be served in their place from `override_dirs`, checked in order, and
+// returns the indices of the entries whose bodies changed. files matching
+// the recorded body are left out, so that unmodified entries keep their
+// recorded content as is
+pub fn apply_overrides(har: &mut Har, override_dirs: &[PathBuf]) -> Result<Vec<usize>> {
+    let mut changed = Vec::new();
+    for entry in har.entries.iter_mut().filter(|entry| !entry.is_data_uri()) {
+        let Some(path) = find_override(entry, override_dirs)? else {
+            continue;
+        };
+        let body = std::fs::read(&path)?;
+        if entry.res_body().is_some_and(|recorded| recorded == body) {
+            continue;
+        }
+        info!("{}: embedding body from file {}", entry, path.display());
+        entry.set_res_body(&body);
+        changed.push(entry.index());
+    }
+    Ok(changed)
+}
@@ -1,7 +1,17 @@
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::join;
 
-use crate::{blackhole::build_blackhole, dump::dump, har::Har, server::build_server};
+use crate::{
+    blackhole::build_blackhole,
+    config::{ConfigFile, DEFAULT_CONFIG_PATH},
+    dump::{dump, DumpOptions},
+    har::Har,
+    server::{build_server, ServerOptions},
+};
+
+const HEALTH_CHECK_ATTEMPTS: usize = 50;
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 fn prompt_yes_or_no() -> Option<bool> {
     let mut response = String::new();
@@ -20,33 +30,114 @@ fn har_guide() -> Har {
     let mut har_path = String::new();
     std::io::stdin().read_line(&mut har_path).unwrap();
     let har_path = PathBuf::from(har_path.trim());
-    let har = match Har::read(&har_path) {
+    let mut har = match Har::read(&har_path) {
         Ok(har) => har,
         Err(e) => {
             println!("Error reading HAR file: {}", e);
             std::process::exit(1);
         }
     };
+    if har.pages().len() > 1 {
+        println!();
+        println!("The HAR has {} pages:", har.pages().len());
+        for (i, page) in har.pages().iter().enumerate() {
+            println!("  {}. {} ({})", i + 1, page.title, page.id);
+        }
+        println!("Which page's entries would you like to use? (Default 1):");
+        let mut choice = String::new();
+        std::io::stdin().read_line(&mut choice).unwrap();
+        let choice = choice.trim().parse::<usize>().unwrap_or(1);
+        let Some(page) = choice.checked_sub(1).and_then(|i| har.pages().get(i)) else {
+            println!("Invalid page");
+            std::process::exit(1);
+        };
+        let page_id = page.id.clone();
+        har = match Har::read_page(&har_path, Some(&page_id)) {
+            Ok(har) => har,
+            Err(e) => {
+                println!("Error reading HAR file: {}", e);
+                std::process::exit(1);
+            }
+        };
+    }
     println!();
     println!(
-        "Got HAR for url {} ({} entries)",
+        "Got HAR for url {} ({} entries across {} hosts)",
         har.primary_url(),
-        har.entries.len()
+        har.entries.len(),
+        har.unique_hosts().len()
     );
+    if let Ok(origin_host) = har.origin_host() {
+        let third_party = har
+            .entries
+            .iter()
+            .filter(|entry| entry.is_third_party(&origin_host))
+            .count();
+        if third_party > 0 {
+            println!(
+                "{} entries are from hosts other than {}, and will be served under /<host>/",
+                third_party, origin_host
+            );
+        }
+    }
     har
 }
 
-fn dump_guide(har: &Har) -> Option<PathBuf> {
+async fn dump_guide(har: &Har) -> Option<PathBuf> {
     println!("Harbinger will now dump the HAR file to disk, unminifying any javascript it finds.");
     println!("Where would you like to dump the HAR file? (e.g. /home/user/dump):");
     let mut dump_path = String::new();
     std::io::stdin().read_line(&mut dump_path).unwrap();
     let dump_path = Path::new(dump_path.trim()).to_path_buf();
     println!("Dumping HAR to {}", dump_path.display());
-    dump(har, &dump_path, false).unwrap();
+    dump(har, &dump_path, &DumpOptions::default())
+        .await
+        .unwrap();
     Some(dump_path)
 }
 
+// saves the server settings picked in the guide, keeping anything else
+// already in the config file
+fn save_config(
+    port: u16,
+    blackhole_port: u16,
+    dump_path: Option<&Path>,
+    proxy: Option<&reqwest::Url>,
+) {
+    let path = Path::new(DEFAULT_CONFIG_PATH);
+    let mut config = if path.exists() {
+        match ConfigFile::read(path) {
+            Ok(config) => config,
+            Err(e) => {
+                println!(
+                    "Error reading {}, not saving settings: {}",
+                    path.display(),
+                    e
+                );
+                return;
+            }
+        }
+    } else {
+        ConfigFile::default()
+    };
+    config.set("serve", "port", i64::from(port));
+    config.set("serve", "blackhole_port", i64::from(blackhole_port));
+    if let Some(dump_path) = dump_path {
+        config.set(
+            "serve",
+            "dump_path",
+            dump_path.to_string_lossy().to_string(),
+        );
+    }
+    if let Some(proxy) = proxy {
+        config.set("serve", "proxy", proxy.to_string());
+    }
+    match config.write(path) {
+        Ok(()) => println!("Saved settings to {}", path.display()),
+        Err(e) => println!("Error saving settings: {}", e),
+    }
+}
+
 async fn server_guide(har: &Har, dump_path: Option<PathBuf>) {
     println!("Would you like to serve the HAR file? (y/n):");
     match prompt_yes_or_no() {
@@ -92,6 +183,25 @@ async fn server_guide(har: &Har, dump_path: Option<PathBuf>) {
         }
     };
 
+    println!();
+    println!(
+        "Would you like to save these settings to {}, so that `harbinger serve` uses them next time? (y/n):",
+        DEFAULT_CONFIG_PATH
+    );
+    match prompt_yes_or_no() {
+        Some(true) => save_config(
+            port,
+            blackhole_port,
+            dump_path.as_deref(),
+            proxy_server.as_ref(),
+        ),
+        Some(false) => {}
+        _ => {
+            println!("Invalid response");
+            std::process::exit(1);
+        }
+    };
+
     println!();
     println!("To utilize the blackhole server, and thus prevent requests from leaving your network, you'll need to configure your browser to use it as a proxy.");
     println!("This can be done by launching your browser from the command line like this:");
@@ -99,15 +209,47 @@ async fn server_guide(har: &Har, dump_path: Option<PathBuf>) {
         "  google-chrome --proxy-server=http://localhost:{} --proxy-bypass-list=localhost",
         blackhole_port
     );
-    println!("Once you've launched your browser, navigate to http://localhost:{}/harbinger to activate Harbinger's service worker. Press enter once you've done this.", port);
-    std::io::stdin().read_line(&mut String::new()).unwrap();
 
     println!();
     println!("Starting the server...");
-    let harbinger_server = build_server(har, port, dump_path.as_ref(), proxy_server.as_ref())
-        .expect("failed to initialize server from HAR");
-    let blackhole = build_blackhole(port);
-    let _ = join!(harbinger_server.launch(), blackhole.launch());
+    let options = ServerOptions {
+        port,
+        dump_path,
+        proxy: proxy_server,
+        ..Default::default()
+    };
+    let harbinger_server =
+        build_server(har, &options).expect("failed to initialize server from HAR");
+    let blackhole = build_blackhole(blackhole_port);
+    let _ = join!(harbinger_server.launch(), blackhole.launch(), async {
+        match wait_for_server(port).await {
+            Some(health) => println!(
+                "Harbinger is serving {} entries from {}.",
+                health["entries"],
+                health["origin_host"].as_str().unwrap_or_default()
+            ),
+            None => {
+                println!("Harbinger didn't start on port {}.", port);
+                std::process::exit(1);
+            }
+        }
+        println!("Once you've launched your browser, navigate to http://localhost:{}/harbinger to activate Harbinger's service worker.", port);
+    });
+}
+
+// polls the server's health endpoint until it answers, returning its status,
+// or None if it doesn't within a few seconds
+async fn wait_for_server(port: u16) -> Option<serde_json::Value> {
+    let url = format!("http://localhost:{}/harbinger/health", port);
+    for _ in 0..HEALTH_CHECK_ATTEMPTS {
+        if let Ok(res) = reqwest::get(&url).await {
+            if res.status().is_success() {
+                return serde_json::from_str(&res.text().await.ok()?).ok();
+            }
+        }
+        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    }
+    None
 }
 
 pub async fn run() {
@@ -119,7 +261,7 @@ pub async fn run() {
     println!();
     let har = har_guide();
     println!();
-    let dump_path = dump_guide(&har);
+    let dump_path = dump_guide(&har).await;
     println!();
     server_guide(&har, dump_path).await;
 }
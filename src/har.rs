@@ -1,64 +1,465 @@
 use anyhow::Result;
 use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, FixedOffset};
 use har::{
-    v1_2::{Entries, Headers, Log},
+    v1_2::{Creator, Entries, Headers, Log},
     Har as HarExt,
 };
-use log::warn;
+use log::{debug, log, Level};
 use rocket::http::{uri, Method};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
+    borrow::{Borrow, Cow},
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt,
     fs::File,
+    io::Read,
+    net::IpAddr,
     path::{Path, PathBuf},
 };
+use url::Url;
 
 use crate::error::HarbingerError;
 
-fn read_v1_2_har(path: &Path) -> Result<Log> {
-    let reader = File::open(path)?;
-    match serde_json::from_reader::<File, HarExt>(reader)?.log {
+pub const INVALID_HOST: &str = "<invalid>";
+
+// Chrome records a WebSocket's frames in this non-standard entry field, which
+// the har crate's types don't have
+const WS_MESSAGES_FIELD: &str = "_webSocketMessages";
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReadOptions<'a> {
+    // only read the entries of the page with this id
+    pub page_id: Option<&'a str>,
+    // log problems with the HAR's structure that don't lose any data (like
+    // mismatched pagerefs) at debug rather than warn level
+    pub suppress_warnings: bool,
+}
+
+impl ReadOptions<'_> {
+    fn structural_warning_level(&self) -> Level {
+        if self.suppress_warnings {
+            Level::Debug
+        } else {
+            Level::Warn
+        }
+    }
+}
+
+// HAR 1.3 only adds fields to 1.2, so a 1.3 log is read as a 1.2 one by
+// round-tripping it through JSON, which drops the additions
+fn v1_2_log(har: HarExt) -> Result<Log> {
+    match har.log {
         har::Spec::V1_2(log) => Ok(log),
-        _ => Err(HarbingerError::UnsupportedHarVersion.into()),
+        har::Spec::V1_3(log) => Ok(serde_json::from_value(serde_json::to_value(log)?)?),
+    }
+}
+
+// reads a HAR 1.2 or 1.3 file as a 1.2 log
+pub fn read_har(path: &Path) -> Result<Log> {
+    let reader = File::open(path)?;
+    v1_2_log(serde_json::from_reader::<File, HarExt>(reader)?)
+}
+
+// a WebSocket frame recorded in WS_MESSAGES_FIELD
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WsMessage {
+    // "send" for frames from the browser, "receive" for frames from the
+    // server
+    #[serde(rename = "type")]
+    pub kind: String,
+    // seconds since the epoch
+    pub time: f64,
+    // 1 for text frames, and 2 for binary frames, whose data is base64
+    // encoded
+    pub opcode: u8,
+    pub data: String,
+}
+
+impl WsMessage {
+    pub fn is_received(&self) -> bool {
+        self.kind == "receive"
+    }
+}
+
+// an event from a recorded text/event-stream response
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    // the event's data lines, joined by newlines
+    pub data: Option<String>,
+    pub id: Option<String>,
+    // reconnection time in milliseconds
+    pub retry: Option<u64>,
+}
+
+// splits an event stream into events at blank lines. comments and unknown
+// fields are dropped, as are blocks left with no fields
+pub fn parse_sse_events(body: &str) -> Vec<SseEvent> {
+    let body = body.replace("\r\n", "\n").replace('\r', "\n");
+    let mut events = Vec::new();
+    for block in body.split("\n\n") {
+        let mut event = SseEvent::default();
+        for line in block.lines().filter(|line| !line.starts_with(':')) {
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value).to_string();
+            match field {
+                "event" => event.event = Some(value),
+                "data" => match &mut event.data {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(&value);
+                    }
+                    None => event.data = Some(value),
+                },
+                "id" => event.id = Some(value),
+                "retry" => event.retry = value.parse().ok().or(event.retry),
+                _ => {}
+            }
+        }
+        if event != SseEvent::default() {
+            events.push(event);
+        }
     }
+    events
+}
+
+// the WS_MESSAGES_FIELD of each entry in a HAR's JSON, in entry order
+fn read_ws_messages(value: &serde_json::Value) -> Vec<Vec<WsMessage>> {
+    value["log"]["entries"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|entry| {
+            entry
+                .get(WS_MESSAGES_FIELD)
+                .and_then(|messages| serde_json::from_value(messages.clone()).ok())
+                .unwrap_or_default()
+        })
+        .collect()
 }
 
 pub struct Har {
     pub entries: Vec<Entry>,
-    pub page_id: String,
+    pages: Vec<Page>,
+    // everything but the entries, kept so the HAR can be serialized back
+    log: Log,
+}
+
+#[derive(Clone, Debug)]
+pub struct Page {
+    pub id: String,
+    pub title: String,
+    pub started_date_time: String,
 }
 
 impl Har {
-    pub fn new(mut har: Log) -> Self {
-        let pages = har.pages.as_ref().unwrap();
-        if pages.len() > 1 {
-            warn!("multiple HAR pages not supported, only using first page");
+    // loads the entries of the page with id `options.page_id`, or every
+    // entry if None, in which case the first page is the primary one
+    pub fn new(mut har: Log, options: ReadOptions) -> Result<Self> {
+        let page_id = options.page_id;
+        let mut pages: Vec<Page> = har
+            .pages
+            .iter()
+            .flatten()
+            .map(|page| Page {
+                id: page.id.clone(),
+                title: page.title.clone(),
+                started_date_time: page.started_date_time.clone(),
+            })
+            .collect();
+        if let Some(page_id) = page_id {
+            let Some(position) = pages.iter().position(|page| page.id == page_id) else {
+                return Err(HarbingerError::UnknownPage {
+                    id: page_id.to_string(),
+                }
+                .into());
+            };
+            // the selected page becomes the primary page
+            let page = pages.remove(position);
+            pages.insert(0, page);
+        } else if pages.len() > 1 {
+            log!(
+                options.structural_warning_level(),
+                "multiple HAR pages not supported, only using first page (pick one with --page)"
+            );
         }
-        let page_id = pages[0].id.clone();
+        // HARs written by --record-new may not have any pages
+        let primary_page_id = pages.first().map(|page| page.id.as_str());
         let entries = har
             .entries
             .drain(..)
-            .map(|entry| {
-                if entry.pageref.as_ref() != Some(&page_id) {
-                    warn!(
+            .enumerate()
+            .map(|(index, entry)| Entry::new(index, entry))
+            .filter(|entry| page_id.is_none() || entry.page_id() == page_id)
+            .inspect(|entry| {
+                if primary_page_id.is_some() && entry.page_id() != primary_page_id {
+                    log!(
+                        options.structural_warning_level(),
                         "entry {}: expected pagref {:?}, got {}",
-                        &entry.request.url, &entry.pageref, &page_id
+                        entry.url(),
+                        entry.page_id(),
+                        primary_page_id.unwrap()
                     );
                 }
-                Entry::new(entry)
             })
             .collect();
-        Har { entries, page_id }
+        Ok(Har {
+            entries,
+            pages,
+            log: har,
+        })
+    }
+
+    pub fn from_json_value(value: serde_json::Value) -> Result<Self> {
+        Har::from_json_with(value, ReadOptions::default())
+    }
+
+    // like Har::new, but also reading the WebSocket frames the har crate's
+    // types leave out
+    fn from_json_with(value: serde_json::Value, options: ReadOptions) -> Result<Self> {
+        let mut ws_messages = read_ws_messages(&value);
+        let log = v1_2_log(serde_json::from_value::<HarExt>(value)?)?;
+        let mut har = Har::new(log, options)?;
+        for entry in &mut har.entries {
+            if let Some(messages) = ws_messages.get_mut(entry.index) {
+                entry.ws_messages = std::mem::take(messages);
+            }
+        }
+        Ok(har)
+    }
+
+    pub fn to_log(&self) -> Log {
+        let mut log = self.log.clone();
+        log.entries = self
+            .entries
+            .iter()
+            .map(|entry| entry.inner.clone())
+            .collect();
+        log
+    }
+
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let har = HarExt {
+            log: har::Spec::V1_2(self.to_log()),
+        };
+        let mut value = serde_json::to_value(har).expect("HAR types always serialize");
+        if let Some(entries) = value["log"]["entries"].as_array_mut() {
+            for (value, entry) in entries.iter_mut().zip(&self.entries) {
+                if !entry.ws_messages.is_empty() {
+                    value[WS_MESSAGES_FIELD] = serde_json::to_value(&entry.ws_messages)
+                        .expect("WebSocket messages always serialize");
+                }
+            }
+        }
+        value
+    }
+
+    pub fn pages(&self) -> &[Page] {
+        &self.pages
+    }
+
+    // combines two HARs, with `other`'s entries following this one's. this
+    // HAR's first page stays the primary page, and `other`'s pages are added
+    // after it, renumbered if their id is already taken. entries for a
+    // method and URL that both HARs have are all kept
+    pub fn merge(self, other: Har) -> Har {
+        self.merge_with(other, MergeStrategy::All)
+    }
+
+    // merge, with `strategy` deciding what happens to entries for a method
+    // and URL that both HARs have
+    pub fn merge_with(mut self, mut other: Har, strategy: MergeStrategy) -> Har {
+        let own_ids: HashSet<String> = self.pages.iter().map(|page| page.id.clone()).collect();
+        let mut taken: HashSet<String> = own_ids
+            .iter()
+            .cloned()
+            .chain(other.pages.iter().map(|page| page.id.clone()))
+            .collect();
+        let mut renamed = HashMap::new();
+        for page in &other.pages {
+            if own_ids.contains(&page.id) {
+                let id = (1..)
+                    .map(|n| format!("page_{}", n))
+                    .find(|id| !taken.contains(id))
+                    .unwrap();
+                taken.insert(id.clone());
+                renamed.insert(page.id.clone(), id);
+            }
+        }
+        for page in &mut other.pages {
+            if let Some(id) = renamed.get(&page.id) {
+                page.id = id.clone();
+            }
+        }
+        for page in other.log.pages.iter_mut().flatten() {
+            if let Some(id) = renamed.get(&page.id) {
+                page.id = id.clone();
+            }
+        }
+        for entry in &mut other.entries {
+            if let Some(id) = entry.page_id().and_then(|id| renamed.get(id)) {
+                entry.inner.pageref = Some(id.clone());
+            }
+        }
+
+        let request_keys = |har: &Har| -> HashSet<(String, String)> {
+            har.entries.iter().map(Entry::request_key).collect()
+        };
+        match strategy {
+            MergeStrategy::First => {
+                let existing = request_keys(&self);
+                other
+                    .entries
+                    .retain(|entry| !existing.contains(&entry.request_key()));
+            }
+            MergeStrategy::Last => {
+                let replacing = request_keys(&other);
+                self.entries
+                    .retain(|entry| !replacing.contains(&entry.request_key()));
+            }
+            MergeStrategy::All => {}
+        }
+
+        let entries = self
+            .entries
+            .into_iter()
+            .chain(other.entries)
+            .enumerate()
+            .map(|(index, entry)| Entry { index, ..entry })
+            .collect();
+        self.pages.extend(other.pages);
+        if let Some(other_pages) = other.log.pages {
+            self.log
+                .pages
+                .get_or_insert_with(Vec::new)
+                .extend(other_pages);
+        }
+        Har {
+            entries,
+            pages: self.pages,
+            log: self.log,
+        }
+    }
+
+    // a copy of this HAR with only the entries `predicate` accepts, in the
+    // same order and keeping their original indices and pages
+    pub fn filter(&self, predicate: impl Fn(&Entry) -> bool + Send + Sync + 'static) -> Har {
+        Har {
+            entries: self
+                .entries
+                .iter()
+                .filter(|entry| predicate(entry))
+                .cloned()
+                .collect(),
+            pages: self.pages.clone(),
+            log: self.log.clone(),
+        }
+    }
+
+    pub fn filter_by_host(&self, host: &str) -> Har {
+        let host = host.to_string();
+        self.filter(move |entry| {
+            entry
+                .hostname()
+                .is_ok_and(|hostname| hostname.eq_ignore_ascii_case(&host))
+        })
+    }
+
+    pub fn filter_by_status(&self, status: i64) -> Har {
+        self.filter(move |entry| entry.status() == status)
+    }
+
+    pub fn filter_by_method(&self, method: Method) -> Har {
+        self.filter(move |entry| entry.method().is_ok_and(|m| m == method))
+    }
+
+    // merges each HAR into the first, in order. no HARs merge into an empty
+    // one
+    pub fn merge_all(hars: Vec<Har>) -> Har {
+        Har::merge_all_with(hars, MergeStrategy::All)
+    }
+
+    pub fn merge_all_with(hars: Vec<Har>, strategy: MergeStrategy) -> Har {
+        hars.into_iter()
+            .reduce(|merged, har| merged.merge_with(har, strategy))
+            .unwrap_or_else(Har::empty)
+    }
+
+    fn empty() -> Har {
+        Har {
+            entries: Vec::new(),
+            pages: Vec::new(),
+            log: Log {
+                creator: Creator {
+                    name: "harbinger".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    comment: None,
+                },
+                browser: None,
+                pages: None,
+                entries: Vec::new(),
+                comment: None,
+            },
+        }
+    }
+
+    // reduces each group of entries the server can't tell apart to the one
+    // `strategy` picks. the kept entries stay in order with their original
+    // indices. returns the dropped entries, in order
+    pub fn dedup(&mut self, strategy: DedupStrategy) -> Vec<Entry> {
+        let mut groups: HashMap<_, Vec<usize>> = HashMap::new();
+        for (position, entry) in self.entries.iter().enumerate() {
+            if let Some(key) = entry.dedup_key() {
+                groups.entry(key).or_default().push(position);
+            }
+        }
+        let mut dropped = HashSet::new();
+        for positions in groups.into_values() {
+            let kept = match strategy {
+                DedupStrategy::First => positions[0],
+                DedupStrategy::Last => positions[positions.len() - 1],
+                DedupStrategy::Status2xxFirst => positions
+                    .iter()
+                    .copied()
+                    .find(|&position| (200..300).contains(&self.entries[position].status()))
+                    .unwrap_or(positions[0]),
+            };
+            dropped.extend(positions.into_iter().filter(|&position| position != kept));
+        }
+        let mut removed = Vec::new();
+        for (position, entry) in std::mem::take(&mut self.entries).into_iter().enumerate() {
+            if dropped.contains(&position) {
+                removed.push(entry);
+            } else {
+                self.entries.push(entry);
+            }
+        }
+        removed
+    }
+
+    // writes the HAR out as HAR 1.2 JSON
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(&self.to_json_value())?)?;
+        Ok(())
     }
 
-    pub fn entries(&self) -> Result<HashMap<(Method, String), Vec<&Entry>>> {
+    // entries grouped by method, URL without query, and request body hash
+    pub fn entries(&self) -> Result<HashMap<(Method, String, Option<String>), Vec<&Entry>>> {
         let mut map = HashMap::new();
         for entry in &self.entries {
+            if entry.is_data_uri() {
+                debug!("skipping data URI entry #{}", entry.index());
+                continue;
+            }
             let method = entry.method()?;
             let uri = entry.uri()?;
             let uri_without_query_or_fragment =
                 format!("{}{}", uri.authority().unwrap(), uri.path());
             let matching_entries = map
-                .entry((method, uri_without_query_or_fragment))
+                .entry((method, uri_without_query_or_fragment, entry.req_body_hash()))
                 .or_insert(Vec::new());
             matching_entries.push(entry);
         }
@@ -66,24 +467,227 @@ impl Har {
     }
 
     pub fn read(path: &Path) -> Result<Self> {
-        let log = read_v1_2_har(path)?;
-        Ok(Har::new(log))
+        Har::read_with(path, ReadOptions::default())
+    }
+
+    // reads only the entries of the page with id `page_id`, if given
+    pub fn read_page(path: &Path, page_id: Option<&str>) -> Result<Self> {
+        Har::read_with(
+            path,
+            ReadOptions {
+                page_id,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn read_with(path: &Path, options: ReadOptions) -> Result<Self> {
+        let value = serde_json::from_reader(File::open(path)?)?;
+        Har::from_json_with(value, options)
+    }
+
+    // the entry the HAR was recorded for: the first one with a host, since
+    // browsers may record inline data URIs ahead of the page itself. HARs of
+    // nothing but data URIs fall back to their first entry
+    fn primary_entry(&self) -> &Entry {
+        self.entries
+            .iter()
+            .find(|entry| !entry.is_data_uri())
+            .unwrap_or(&self.entries[0])
     }
 
     pub fn primary_url(&self) -> &str {
-        &self.entries[0].inner.request.url
+        self.primary_entry().url()
+    }
+
+    // number of entries per hostname, with entries whose hostname can't be
+    // parsed counted under "<invalid>"
+    pub fn entry_count_by_host(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for entry in &self.entries {
+            let host = entry
+                .hostname()
+                .unwrap_or_else(|_| INVALID_HOST.to_string());
+            *counts.entry(host).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn unique_hosts(&self) -> Vec<String> {
+        self.entry_count_by_host()
+            .into_keys()
+            .filter(|host| host != INVALID_HOST)
+            .collect()
     }
 
     pub fn origin_host(&self) -> Result<String> {
-        let uri = self.entries[0].uri()?;
+        let uri = self.primary_entry().uri()?;
         let host = uri.authority().unwrap().host().to_string();
         Ok(host)
     }
+
+    // entries sorted by when they started, paired with the seconds elapsed
+    // since the first one started. entries without a parseable start time
+    // are placed at the end, at the latest start time
+    pub fn response_timeline(&self) -> Vec<(f64, &Entry)> {
+        let mut started: Vec<(DateTime<FixedOffset>, &Entry)> = Vec::new();
+        let mut unstarted = Vec::new();
+        for entry in &self.entries {
+            match DateTime::parse_from_rfc3339(&entry.inner.started_date_time) {
+                Ok(start) => started.push((start, entry)),
+                Err(_) => unstarted.push(entry),
+            }
+        }
+        started.sort_by_key(|(start, _)| *start);
+        let elapsed_since = |first: DateTime<FixedOffset>, start: DateTime<FixedOffset>| {
+            (start - first).num_microseconds().unwrap_or(0) as f64 / 1_000_000.0
+        };
+        let mut timeline: Vec<(f64, &Entry)> = match started.first() {
+            Some((first, _)) => started
+                .iter()
+                .map(|(start, entry)| (elapsed_since(*first, *start), *entry))
+                .collect(),
+            None => Vec::new(),
+        };
+        let last_elapsed = timeline.last().map_or(0.0, |(elapsed, _)| *elapsed);
+        timeline.extend(unstarted.into_iter().map(|entry| (last_elapsed, entry)));
+        timeline
+    }
+}
+
+// what counts as first-party when classifying entries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PartyScope {
+    // only the origin host itself
+    Host,
+    // the origin host's registrable domain and all of its subdomains
+    Site,
+}
+
+// the ASCII (punycode) form of a hostname, lowercased, so that a Unicode
+// IDN and its punycode form compare equal. hosts that aren't valid domain
+// names are only lowercased
+pub fn ascii_host(host: &str) -> String {
+    idna::domain_to_ascii(host).unwrap_or_else(|_| host.to_ascii_lowercase())
+}
+
+// `url` with a Unicode host replaced by its punycode form, since rocket's URI
+// parser only accepts ASCII hosts
+fn with_ascii_host(url: &str) -> Cow<'_, str> {
+    let Some(start) = url.find("://").map(|index| index + 3) else {
+        return Cow::Borrowed(url);
+    };
+    let end = url[start..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |index| start + index);
+    let authority = &url[start..end];
+    let host_start = authority.rfind('@').map_or(0, |index| index + 1);
+    let host_end = authority[host_start..]
+        .rfind(':')
+        .map_or(authority.len(), |index| host_start + index);
+    let host = &authority[host_start..host_end];
+    if host.is_ascii() {
+        return Cow::Borrowed(url);
+    }
+    match idna::domain_to_ascii(host) {
+        Ok(ascii) => Cow::Owned(format!(
+            "{}{}{}",
+            &url[..start + host_start],
+            ascii,
+            &url[start + host_end..]
+        )),
+        Err(_) => Cow::Borrowed(url),
+    }
+}
+
+// the host's eTLD+1 per the public suffix list, e.g. example.co.uk for
+// www.example.co.uk. IP addresses, and hosts that are themselves public
+// suffixes (or have no dots, like localhost), are their own site
+fn registrable_domain(host: &str) -> &str {
+    let unbracketed = host.trim_start_matches('[').trim_end_matches(']');
+    if unbracketed.parse::<IpAddr>().is_ok() {
+        return host;
+    }
+    psl::domain_str(host).unwrap_or(host)
+}
+
+// which entries merging HARs keeps when more than one HAR has an entry for
+// the same method and URL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum MergeStrategy {
+    // only those from the earliest HAR
+    First,
+    // only those from the latest HAR
+    Last,
+    // all of them, in the order the HARs were given
+    All,
+}
+
+// which entry deduplicating keeps out of those for the same request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum DedupStrategy {
+    // the earliest recorded
+    First,
+    // the latest recorded
+    Last,
+    // the earliest with a 2xx status, or the earliest if none has one
+    #[cfg_attr(feature = "cli", value(name = "status-2xx-first"))]
+    Status2xxFirst,
+}
+
+// orders entries can be processed or listed in, other than recording order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum EntrySort {
+    // alphabetically by URL
+    Url,
+    // largest response body first
+    SizeDesc,
+    // grouped by response MIME type
+    Type,
+}
+
+impl EntrySort {
+    // the sorts are stable, so entries that compare equal stay in recording
+    // order
+    pub fn sort<E: Borrow<Entry>>(self, entries: &mut [E]) {
+        match self {
+            EntrySort::Url => entries.sort_by(|a, b| a.borrow().url().cmp(b.borrow().url())),
+            EntrySort::SizeDesc => entries.sort_by_cached_key(|entry| {
+                Reverse(entry.borrow().res_body().map_or(0, |body| body.len()))
+            }),
+            EntrySort::Type => {
+                entries.sort_by(|a, b| a.borrow().content_type().cmp(&b.borrow().content_type()))
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Entry {
     inner: Entries,
+    index: usize,
+    // the response's MIME type, lowercased and without parameters
+    content_type: Option<String>,
+    // the frames of a WebSocket entry, if any were recorded
+    ws_messages: Vec<WsMessage>,
+}
+
+// decodes a body compressed with the given content-encoding, or None if the
+// encoding isn't supported or the body isn't actually compressed, which is
+// usual since most browsers record decoded bodies
+fn decompress(encoding: &str, body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let result = match encoding.trim().to_ascii_lowercase().as_str() {
+        "gzip" | "x-gzip" => flate2::read::GzDecoder::new(body).read_to_end(&mut decoded),
+        "deflate" => flate2::read::ZlibDecoder::new(body).read_to_end(&mut decoded),
+        "br" => brotli::Decompressor::new(body, 4096).read_to_end(&mut decoded),
+        _ => return None,
+    };
+    result.ok().map(|_| decoded)
 }
 
 // truncates a string to a given length, less the size of its md5 hash
@@ -94,47 +698,125 @@ fn uniquely_truncate(s: &str, limit: usize) -> String {
 }
 
 impl Entry {
-    pub fn new(inner: Entries) -> Entry {
-        Entry { inner }
+    pub fn new(index: usize, inner: Entries) -> Entry {
+        let mut entry = Entry {
+            inner,
+            index,
+            content_type: None,
+            ws_messages: Vec::new(),
+        };
+        entry.content_type = entry.response_mime().map(str::to_ascii_lowercase);
+        entry
+    }
+
+    // inline resources (e.g. images embedded in CSS) some browsers record,
+    // which have no host or path to serve them at
+    pub fn is_data_uri(&self) -> bool {
+        self.url()
+            .get(..5)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+    }
+
+    pub fn page_id(&self) -> Option<&str> {
+        self.inner.pageref.as_deref()
+    }
+
+    // identifies entries for the same request across HARs
+    fn request_key(&self) -> (String, String) {
+        (
+            self.inner.request.method.to_ascii_uppercase(),
+            self.url().to_string(),
+        )
+    }
+
+    // identifies entries for requests the server can't tell apart: the same
+    // method, URL without its fragment or an empty query, and request body.
+    // None for data URIs and entries whose method or URL can't be parsed
+    fn dedup_key(&self) -> Option<(Method, String, Option<String>)> {
+        if self.is_data_uri() {
+            return None;
+        }
+        let method = self.method().ok()?;
+        let uri = self.uri().ok()?;
+        let mut url = format!("{}{}", uri.authority()?, uri.path());
+        if let Some(query) = uri.query().map(|q| q.as_str()).filter(|q| !q.is_empty()) {
+            url.push('?');
+            url.push_str(query);
+        }
+        Some((method, url, self.req_body_hash()))
+    }
+
+    // position of this entry in the original HAR
+    pub fn index(&self) -> usize {
+        self.index
     }
 
     pub fn get_dump_path(&self, base_path: &Path) -> Result<PathBuf> {
-        let url = self
-            .inner
-            .request
-            .url
-            .replace("http://", "")
-            .replace("https://", "");
+        let url = self.url().replace("http://", "").replace("https://", "");
+        let mut parts: Vec<String> = Path::new(&url)
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy().to_string())
+            .collect();
+        if url.ends_with('/') {
+            parts.push("__index__".to_string());
+        }
+        // distinguish e.g. different POST calls to the same URL. the hash is
+        // added before truncating, so the file name stays under NAME_MAX
+        if let (Some(body_hash), Some(file_name)) = (self.dump_body_hash(), parts.last_mut()) {
+            *file_name = format!("{}_{}", file_name, body_hash);
+        }
         let mut path = base_path.to_path_buf();
         path.push(self.method()?.to_string());
-        for part in Path::new(&url).components() {
-            if part.as_os_str().len() > 200 {
-                let s = part.as_os_str().to_str().unwrap();
-                path.push(uniquely_truncate(s, 200))
+        for part in parts {
+            if part.len() > 200 {
+                path.push(uniquely_truncate(&part, 200));
             } else {
                 path.push(part);
             }
         }
-        if url.ends_with('/') {
-            path.push("__index__");
-        }
         Ok(path)
     }
 
+    // a file name for the entry that encodes its whole URL, for dumping
+    // every entry into a single directory
+    pub fn get_flat_dump_name(&self) -> String {
+        let url = self.url().replace("http://", "").replace("https://", "");
+        let mut name = url.replace(['/', '\\'], "_");
+        if let Some(body_hash) = self.dump_body_hash() {
+            name = format!("{}_{}", name, body_hash);
+        }
+        if name.len() > 200 {
+            name = uniquely_truncate(&name, 200);
+        }
+        name
+    }
+
+    pub fn url(&self) -> &str {
+        &self.inner.request.url
+    }
+
+    // the entry's URL without its fragment. fragments are client-side only,
+    // so they never reach (or route to) the server, and SPA hash routes
+    // often contain characters rocket's parser rejects
     pub fn uri(&self) -> Result<uri::Reference> {
-        let req_uri = self.inner.request.url.as_str();
-        let parsed = uri::Uri::parse::<uri::Reference>(req_uri).map_err(|err| {
+        let req_uri = self.url();
+        let without_fragment = with_ascii_host(req_uri.split('#').next().unwrap());
+        let parsed = uri::Uri::parse::<uri::Reference>(&without_fragment).map_err(|err| {
             dbg!(err);
             HarbingerError::InvalidHarEntryUri {
                 uri: req_uri.to_string(),
             }
         })?;
-        parsed.reference().cloned().ok_or(
-            HarbingerError::InvalidHarEntryUri {
-                uri: req_uri.to_string(),
-            }
-            .into(),
-        )
+        parsed
+            .reference()
+            .cloned()
+            .map(uri::Reference::into_owned)
+            .ok_or(
+                HarbingerError::InvalidHarEntryUri {
+                    uri: req_uri.to_string(),
+                }
+                .into(),
+            )
     }
 
     pub fn method(&self) -> Result<Method> {
@@ -148,7 +830,37 @@ impl Entry {
     }
 
     pub fn hostname(&self) -> Result<String> {
-        Ok(self.uri()?.authority().unwrap().host().to_string())
+        let uri = self.uri()?;
+        let authority = uri
+            .authority()
+            .ok_or_else(|| HarbingerError::InvalidHarEntryUri {
+                uri: self.url().to_string(),
+            })?;
+        Ok(authority.host().to_string())
+    }
+
+    // whether the entry was served from a host other than `origin_host`, with
+    // subdomains of the origin counting as third-party
+    pub fn is_third_party(&self, origin_host: &str) -> bool {
+        self.is_third_party_in(origin_host, PartyScope::Host)
+    }
+
+    pub fn is_third_party_in(&self, origin_host: &str, scope: PartyScope) -> bool {
+        let Ok(hostname) = self.hostname() else {
+            return true;
+        };
+        let normalize = |host: &str| {
+            let host = host.to_ascii_lowercase();
+            match host.rsplit_once(':') {
+                Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host.to_string(),
+                _ => host,
+            }
+        };
+        let (hostname, origin_host) = (normalize(&hostname), normalize(origin_host));
+        match scope {
+            PartyScope::Host => hostname != origin_host,
+            PartyScope::Site => registrable_domain(&hostname) != registrable_domain(&origin_host),
+        }
     }
 
     fn get_header_value<'a>(&self, headers: &'a [Headers], name: &str) -> Option<&'a str> {
@@ -159,10 +871,108 @@ impl Entry {
             .next()
     }
 
+    // where a redirect points, as a path on the harbinger server. the
+    // location is resolved against the entry's URL, and targets on hosts
+    // other than the origin are served under `/<host>`, like the entries
+    // themselves. relative locations are resolved too rather than sent as
+    // recorded, since one from an entry on another host would otherwise
+    // lead out of that host's `/<host>` prefix
+    pub fn redirect_location(&self, origin_host: &str) -> Option<String> {
+        if !matches!(self.status(), 301 | 302 | 303 | 307 | 308) {
+            return None;
+        }
+        let location = self.res_header("location")?;
+        let target = Url::parse(self.url()).ok()?.join(location).ok()?;
+        let host = target.host_str()?;
+        let mut path = target.path().to_string();
+        if let Some(query) = target.query() {
+            path = format!("{}?{}", path, query);
+        }
+        if host.eq_ignore_ascii_case(origin_host) {
+            Some(path)
+        } else {
+            Some(format!("/{}{}", host, path))
+        }
+    }
+
     pub fn res_header(&self, name: &str) -> Option<&str> {
         self.get_header_value(&self.inner.response.headers, name)
     }
 
+    // e.g. "text/html" for "Text/HTML; charset=utf-8"
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    // the raw content-type header, including any parameters
+    pub fn content_type_full(&self) -> Option<&str> {
+        self.res_header("content-type")
+    }
+
+    // the content-type header's MIME type, e.g. "Text/HTML" for
+    // "Text/HTML; charset=utf-8". this borrows from the header, so unlike
+    // content_type() it keeps the header's case
+    pub fn response_mime(&self) -> Option<&str> {
+        let content_type = self.content_type_full()?;
+        let mime = content_type.split(';').next()?.trim();
+        (!mime.is_empty()).then_some(mime)
+    }
+
+    // e.g. `utf-8` for `text/html; charset="utf-8"`
+    pub fn response_charset(&self) -> Option<&str> {
+        self.content_type_full()?
+            .split(';')
+            .skip(1)
+            .find_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("charset")
+                    .then(|| value.trim().trim_matches('"'))
+            })
+    }
+
+    pub fn is_javascript(&self) -> bool {
+        matches!(
+            self.content_type(),
+            Some(
+                "application/javascript"
+                    | "text/javascript"
+                    | "application/x-javascript"
+                    | "application/ecmascript"
+                    | "text/ecmascript"
+            )
+        )
+    }
+
+    // source maps are sometimes served with a javascript content type, so
+    // they're recognized by their extension
+    pub fn is_source_map(&self) -> bool {
+        self.uri()
+            .is_ok_and(|uri| uri.path().as_str().ends_with(".map"))
+    }
+
+    // a WebSocket handshake, whose frames may have been recorded
+    pub fn is_websocket(&self) -> bool {
+        self.status() == 101
+            && self
+                .res_header("upgrade")
+                .is_some_and(|upgrade| upgrade.trim().eq_ignore_ascii_case("websocket"))
+    }
+
+    pub fn ws_messages(&self) -> &[WsMessage] {
+        &self.ws_messages
+    }
+
+    pub fn is_event_stream(&self) -> bool {
+        self.content_type() == Some("text/event-stream")
+    }
+
+    // the events in a text/event-stream response body
+    pub fn sse_events(&self) -> Vec<SseEvent> {
+        let body = self.res_body().unwrap_or_default();
+        parse_sse_events(&String::from_utf8_lossy(&body))
+    }
+
     pub fn res_headers(&self) -> impl Iterator<Item = (&str, &str)> {
         self.inner
             .response
@@ -171,17 +981,580 @@ impl Entry {
             .map(|header| (header.name.as_str(), header.value.as_str()))
     }
 
+    // total time the request took, in milliseconds
+    pub fn time(&self) -> f64 {
+        self.inner.time
+    }
+
+    // milliseconds spent waiting for the server to respond, if recorded
+    pub fn wait_ms(&self) -> Option<u64> {
+        let wait = self.inner.timings.wait;
+        (wait >= 0.0).then(|| wait.round() as u64)
+    }
+
+    // milliseconds spent receiving the response body, if recorded
+    pub fn receive_ms(&self) -> Option<u64> {
+        let receive = self.inner.timings.receive;
+        (receive >= 0.0).then(|| receive.round() as u64)
+    }
+
     pub fn status(&self) -> i64 {
         self.inner.response.status
     }
 
+    // size of the response body as sent, before any content-encoding is
+    // decoded, if it was recorded
+    pub fn transfer_size(&self) -> Option<u64> {
+        let size = self.inner.response.body_size;
+        (size >= 0).then_some(size as u64)
+    }
+
+    // size of the decoded response body, if it was recorded
+    pub fn content_size(&self) -> Option<u64> {
+        let size = self.inner.response.content.size;
+        (size >= 0).then_some(size as u64)
+    }
+
+    pub fn req_body(&self) -> Option<&[u8]> {
+        let post_data = self.inner.request.post_data.as_ref()?;
+        post_data.text.as_ref().map(|text| text.as_bytes())
+    }
+
+    // hex SHA-256 of the request body
+    pub fn req_body_hash(&self) -> Option<String> {
+        self.req_body()
+            .map(|body| format!("{:x}", Sha256::digest(body)))
+    }
+
+    // the request body's hash, for telling apart the dumped files of
+    // requests to the same URL. empty bodies don't need telling apart
+    fn dump_body_hash(&self) -> Option<String> {
+        self.req_body()
+            .filter(|body| !body.is_empty())
+            .map(|body| format!("{:x}", Sha256::digest(body)))
+    }
+
+    // replaces the recorded response body, as text if it's UTF-8 and as
+    // base64 otherwise
+    pub fn set_res_body(&mut self, body: &[u8]) {
+        let content = &mut self.inner.response.content;
+        content.size = body.len() as i64;
+        content.compression = None;
+        match std::str::from_utf8(body) {
+            Ok(text) => {
+                content.text = Some(text.to_string());
+                content.encoding = None;
+            }
+            Err(_) => {
+                content.text = Some(STANDARD.encode(body));
+                content.encoding = Some("base64".to_string());
+            }
+        }
+    }
+
     pub fn res_body(&self) -> Option<Vec<u8>> {
         let body = self.inner.response.content.text.as_ref()?;
         // check if the content is base64 encoded
-        if let Ok(decoded) = STANDARD.decode(body) {
-            Some(decoded)
+        let body = if let Ok(decoded) = STANDARD.decode(body) {
+            decoded
         } else {
-            Some(body.as_bytes().to_vec())
+            body.as_bytes().to_vec()
+        };
+        // content-encoding is never forwarded, so compressed bodies have to
+        // be decoded here
+        match self.res_header("content-encoding") {
+            Some(encoding) => Some(decompress(encoding, &body).unwrap_or(body)),
+            None => Some(body),
         }
     }
 }
+
+// e.g. `[GET] 200 text/html https://example.com/`
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let method = self
+            .method()
+            .map_or("[INVALID]".to_string(), |method| format!("[{}]", method));
+        let url = match self.uri() {
+            Ok(_) => self.url(),
+            Err(_) => "[INVALID]",
+        };
+        write!(
+            f,
+            "{} {} {} {}",
+            method,
+            self.status(),
+            self.content_type().unwrap_or("-"),
+            url
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry_json(
+        url: &str,
+        status: i64,
+        headers: &[(&str, &str)],
+        pageref: Option<&str>,
+    ) -> serde_json::Value {
+        let headers: Vec<serde_json::Value> = headers
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        json!({
+            "pageref": pageref,
+            "startedDateTime": "2023-06-01T12:00:00.000Z",
+            "time": 12.0,
+            "request": {
+                "method": "GET",
+                "url": url,
+                "httpVersion": "HTTP/1.1",
+                "cookies": [],
+                "headers": [],
+                "queryString": [],
+                "headersSize": -1,
+                "bodySize": 0
+            },
+            "response": {
+                "status": status,
+                "statusText": "",
+                "httpVersion": "HTTP/1.1",
+                "cookies": [],
+                "headers": headers,
+                "content": { "size": 0, "mimeType": "" },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": 0
+            },
+            "cache": {},
+            "timings": { "send": 0.0, "wait": 12.0, "receive": 0.0 }
+        })
+    }
+
+    fn entry(url: &str, status: i64, headers: &[(&str, &str)]) -> Entry {
+        let inner = serde_json::from_value(entry_json(url, status, headers, None)).unwrap();
+        Entry::new(0, inner)
+    }
+
+    // a HAR with a page per id, and an entry per `(url, pageref)`
+    fn paged_har(page_ids: &[&str], entries: &[(&str, &str)]) -> Har {
+        let pages: Vec<serde_json::Value> = page_ids
+            .iter()
+            .map(|id| {
+                json!({
+                    "startedDateTime": "2023-06-01T12:00:00.000Z",
+                    "id": id,
+                    "title": id,
+                    "pageTimings": {}
+                })
+            })
+            .collect();
+        let entries: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|(url, pageref)| entry_json(url, 200, &[], Some(pageref)))
+            .collect();
+        Har::from_json_value(json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "harbinger tests", "version": "1.0" },
+                "pages": pages,
+                "entries": entries
+            }
+        }))
+        .unwrap()
+    }
+
+    fn with_content_type(content_type: &str) -> Entry {
+        entry(
+            "https://example.com/",
+            200,
+            &[("Content-Type", content_type)],
+        )
+    }
+
+    #[test]
+    fn content_type_is_normalized() {
+        for (header, expected) in [
+            ("text/html", "text/html"),
+            ("text/html; charset=utf-8", "text/html"),
+            ("Text/HTML; Charset=UTF-8", "text/html"),
+            (
+                "application/javascript;charset=utf-8",
+                "application/javascript",
+            ),
+            ("  application/json ; charset=\"utf-8\"", "application/json"),
+            ("multipart/form-data; boundary=----x", "multipart/form-data"),
+            ("image/svg+xml", "image/svg+xml"),
+        ] {
+            assert_eq!(
+                with_content_type(header).content_type(),
+                Some(expected),
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn content_type_without_a_mime_type() {
+        assert_eq!(with_content_type("").content_type(), None);
+        assert_eq!(with_content_type("; charset=utf-8").content_type(), None);
+        assert_eq!(entry("https://example.com/", 200, &[]).content_type(), None);
+    }
+
+    #[test]
+    fn content_type_full_keeps_parameters() {
+        let entry = with_content_type("Text/HTML; charset=\"UTF-8\"");
+        assert_eq!(
+            entry.content_type_full(),
+            Some("Text/HTML; charset=\"UTF-8\"")
+        );
+        assert_eq!(entry.response_mime(), Some("Text/HTML"));
+        assert_eq!(entry.response_charset(), Some("UTF-8"));
+    }
+
+    #[test]
+    fn ascii_host_normalizes_case_and_idns() {
+        assert_eq!(ascii_host("Example.COM"), "example.com");
+        assert_eq!(ascii_host("bücher.example"), "xn--bcher-kva.example");
+        assert_eq!(ascii_host("xn--bcher-kva.example"), "xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn with_ascii_host_only_rewrites_the_host() {
+        assert_eq!(
+            with_ascii_host("https://bücher.example/bücher?q=ü#ü"),
+            "https://xn--bcher-kva.example/bücher?q=ü#ü"
+        );
+        assert_eq!(
+            with_ascii_host("https://user@bücher.example:8443/"),
+            "https://user@xn--bcher-kva.example:8443/"
+        );
+        assert!(matches!(
+            with_ascii_host("https://example.com/ü"),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(with_ascii_host("/relative"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn uri_drops_the_fragment_and_normalizes_the_host() {
+        let entry = entry("https://bücher.example/page?a=1#/spa/route", 200, &[]);
+        let uri = entry.uri().unwrap();
+        assert_eq!(uri.to_string(), "https://xn--bcher-kva.example/page?a=1");
+        assert_eq!(entry.hostname().unwrap(), "xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn third_party_ignores_case_and_port() {
+        let entry = entry("https://Example.com:8443/", 200, &[]);
+        assert!(!entry.is_third_party("example.com"));
+        assert!(!entry.is_third_party("EXAMPLE.COM:443"));
+        assert!(entry.is_third_party("www.example.com"));
+    }
+
+    // three pages each, with the first recording's app.js also recorded on
+    // the second's first page, and both using page_1 and page_2 as ids
+    fn hars_to_merge() -> (Har, Har) {
+        let first = paged_har(
+            &["page_1", "page_2", "page_3"],
+            &[
+                ("https://example.com/", "page_1"),
+                ("https://example.com/app.js", "page_2"),
+                ("https://example.com/api", "page_3"),
+            ],
+        );
+        let second = paged_har(
+            &["page_1", "page_2", "checkout"],
+            &[
+                ("https://example.com/app.js", "page_1"),
+                ("https://example.com/cart", "page_2"),
+                ("https://example.com/pay", "checkout"),
+            ],
+        );
+        (first, second)
+    }
+
+    // each entry's URL path and pageref, in order
+    fn merged_entries(har: &Har) -> Vec<(String, String)> {
+        har.entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.uri().unwrap().path().to_string(),
+                    entry.page_id().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    fn page_ids(har: &Har) -> Vec<&str> {
+        har.pages().iter().map(|page| page.id.as_str()).collect()
+    }
+
+    fn expected(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+        entries
+            .iter()
+            .map(|(path, page)| (path.to_string(), page.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn merge_renumbers_colliding_pages() {
+        let (first, second) = hars_to_merge();
+        let merged = first.merge(second);
+        assert_eq!(
+            page_ids(&merged),
+            ["page_1", "page_2", "page_3", "page_4", "page_5", "checkout"]
+        );
+        let log_page_ids: Vec<&str> = merged
+            .log
+            .pages
+            .iter()
+            .flatten()
+            .map(|page| page.id.as_str())
+            .collect();
+        assert_eq!(log_page_ids, page_ids(&merged));
+        assert_eq!(
+            merged_entries(&merged),
+            expected(&[
+                ("/", "page_1"),
+                ("/app.js", "page_2"),
+                ("/api", "page_3"),
+                ("/app.js", "page_4"),
+                ("/cart", "page_5"),
+                ("/pay", "checkout"),
+            ])
+        );
+        let indices: Vec<usize> = merged.entries.iter().map(Entry::index).collect();
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn merge_first_keeps_earliest_entries() {
+        let (first, second) = hars_to_merge();
+        let merged = first.merge_with(second, MergeStrategy::First);
+        assert_eq!(page_ids(&merged).len(), 6);
+        assert_eq!(
+            merged_entries(&merged),
+            expected(&[
+                ("/", "page_1"),
+                ("/app.js", "page_2"),
+                ("/api", "page_3"),
+                ("/cart", "page_5"),
+                ("/pay", "checkout"),
+            ])
+        );
+    }
+
+    #[test]
+    fn merge_last_keeps_latest_entries() {
+        let (first, second) = hars_to_merge();
+        let merged = first.merge_with(second, MergeStrategy::Last);
+        assert_eq!(page_ids(&merged).len(), 6);
+        assert_eq!(
+            merged_entries(&merged),
+            expected(&[
+                ("/", "page_1"),
+                ("/api", "page_3"),
+                ("/app.js", "page_4"),
+                ("/cart", "page_5"),
+                ("/pay", "checkout"),
+            ])
+        );
+        let indices: Vec<usize> = merged.entries.iter().map(Entry::index).collect();
+        assert_eq!(indices, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn merge_all_folds_in_order() {
+        let (first, second) = hars_to_merge();
+        let third = paged_har(&["page_1"], &[("https://example.com/api", "page_1")]);
+        let merged = Har::merge_all_with(vec![first, second, third], MergeStrategy::Last);
+        assert_eq!(page_ids(&merged).last(), Some(&"page_6"));
+        assert_eq!(
+            merged_entries(&merged),
+            expected(&[
+                ("/", "page_1"),
+                ("/app.js", "page_4"),
+                ("/cart", "page_5"),
+                ("/pay", "checkout"),
+                ("/api", "page_6"),
+            ])
+        );
+        let empty = Har::merge_all(Vec::new());
+        assert!(empty.entries.is_empty());
+        assert!(empty.pages().is_empty());
+    }
+
+    #[test]
+    fn primary_entry_skips_data_uris() {
+        let har = paged_har(
+            &["page_1"],
+            &[
+                ("data:image/png;base64,iVBORw0KGgo=", "page_1"),
+                ("https://cool.website/app", "page_1"),
+                ("https://cdn.cool.website/app.js", "page_1"),
+            ],
+        );
+        assert_eq!(har.primary_url(), "https://cool.website/app");
+        assert_eq!(har.origin_host().unwrap(), "cool.website");
+    }
+
+    #[test]
+    fn site_scope_uses_public_suffixes() {
+        let third_party = |url: &str, origin_host: &str| {
+            entry(url, 200, &[]).is_third_party_in(origin_host, PartyScope::Site)
+        };
+        assert!(!third_party("https://cdn.example.com/", "www.example.com"));
+        assert!(!third_party(
+            "https://static.example.co.uk/",
+            "example.co.uk"
+        ));
+        // co.uk is a public suffix, so these are different sites
+        assert!(third_party("https://other.co.uk/", "example.co.uk"));
+        assert!(third_party("https://example.com/", "example.co.uk"));
+        assert!(!third_party("http://localhost:8080/", "localhost"));
+    }
+
+    #[test]
+    fn site_scope_compares_ip_addresses_whole() {
+        let third_party = |url: &str, origin_host: &str| {
+            entry(url, 200, &[]).is_third_party_in(origin_host, PartyScope::Site)
+        };
+        assert!(!third_party("http://192.168.0.1:8000/", "192.168.0.1"));
+        assert!(third_party("http://10.168.0.1/", "192.168.0.1"));
+    }
+
+    fn post(url: &str, body: &str) -> Entry {
+        let mut value = entry_json(url, 200, &[], None);
+        value["request"]["method"] = "POST".into();
+        value["request"]["postData"] = json!({
+            "mimeType": "application/json",
+            "params": [],
+            "text": body
+        });
+        Entry::new(0, serde_json::from_value(value).unwrap())
+    }
+
+    #[test]
+    fn dump_paths_of_long_posts_fit_name_max() {
+        let url = format!("https://cool.website/api/search?q={}", "a".repeat(300));
+        let first = post(&url, r#"{"page":1}"#);
+        let second = post(&url, r#"{"page":2}"#);
+        let base = Path::new("dump");
+        let first_path = first.get_dump_path(base).unwrap();
+        let second_path = second.get_dump_path(base).unwrap();
+        assert_ne!(first_path, second_path);
+        for path in [&first_path, &second_path] {
+            let file_name = path.file_name().unwrap();
+            assert!(file_name.len() <= 255, "{}", file_name.len());
+            assert_eq!(
+                path.parent().unwrap(),
+                Path::new("dump/POST/cool.website/api")
+            );
+        }
+        assert!(first.get_flat_dump_name().len() <= 255);
+    }
+
+    #[test]
+    fn dump_paths_hash_nonempty_bodies_only() {
+        let base = Path::new("dump");
+        let path = post("https://cool.website/api/save", "{}")
+            .get_dump_path(base)
+            .unwrap();
+        let hash = format!("{:x}", Sha256::digest(b"{}"));
+        assert_eq!(
+            path,
+            base.join(format!("POST/cool.website/api/save_{}", hash))
+        );
+
+        let empty = post("https://cool.website/api/save", "");
+        assert_eq!(
+            empty.get_dump_path(base).unwrap(),
+            Path::new("dump/POST/cool.website/api/save")
+        );
+        assert_eq!(empty.get_flat_dump_name(), "cool.website_api_save");
+    }
+
+    fn redirect(url: &str, status: i64, location: &str) -> Entry {
+        entry(url, status, &[("Location", location)])
+    }
+
+    #[test]
+    fn redirect_location_on_the_origin() {
+        let entry = redirect(
+            "https://example.com/old",
+            301,
+            "https://example.com/new?a=1",
+        );
+        assert_eq!(
+            entry.redirect_location("example.com").as_deref(),
+            Some("/new?a=1")
+        );
+        let entry = redirect("https://example.com/old", 302, "https://EXAMPLE.com/");
+        assert_eq!(entry.redirect_location("example.com").as_deref(), Some("/"));
+    }
+
+    #[test]
+    fn redirect_location_across_hosts() {
+        let entry = redirect(
+            "https://example.com/login",
+            302,
+            "https://auth.example.net/sso",
+        );
+        assert_eq!(
+            entry.redirect_location("example.com").as_deref(),
+            Some("/auth.example.net/sso")
+        );
+        // and back to the origin from another host
+        let entry = redirect(
+            "https://auth.example.net/sso",
+            303,
+            "https://example.com/home",
+        );
+        assert_eq!(
+            entry.redirect_location("example.com").as_deref(),
+            Some("/home")
+        );
+    }
+
+    #[test]
+    fn redirect_location_resolves_relative_locations() {
+        let entry = redirect("https://example.com/a/b/old", 307, "../new");
+        assert_eq!(
+            entry.redirect_location("example.com").as_deref(),
+            Some("/a/new")
+        );
+        let entry = redirect("https://example.com/a/old", 308, "/root?x=y");
+        assert_eq!(
+            entry.redirect_location("example.com").as_deref(),
+            Some("/root?x=y")
+        );
+        // relative to an entry on another host, so kept under its prefix
+        let entry = redirect("https://cdn.example.net/v1/app.js", 301, "app.min.js");
+        assert_eq!(
+            entry.redirect_location("example.com").as_deref(),
+            Some("/cdn.example.net/v1/app.min.js")
+        );
+    }
+
+    #[test]
+    fn redirect_location_only_for_redirects() {
+        let entry = redirect(
+            "https://example.com/created",
+            201,
+            "https://example.com/item/1",
+        );
+        assert_eq!(entry.redirect_location("example.com"), None);
+        let entry = redirect("https://example.com/", 304, "/other");
+        assert_eq!(entry.redirect_location("example.com"), None);
+        // a redirect without a Location
+        let without_location = entry("https://example.com/old", 301, &[]);
+        assert_eq!(without_location.redirect_location("example.com"), None);
+    }
+}
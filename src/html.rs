@@ -0,0 +1,162 @@
+use anyhow::Result;
+use html5ever::serialize::{serialize, SerializeOpts, TraversalScope};
+use html5ever::tendril::TendrilSink;
+use html5ever::{parse_document, Attribute};
+use markup5ever_rcdom::{Handle, NodeData, RcDom, SerializableHandle};
+
+use crate::js::{parse_js, print_script};
+
+const INDENT: &str = "  ";
+
+// elements that never have children or a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+// elements whose whitespace is significant, which are written as they were
+const PREFORMATTED_ELEMENTS: &[&str] = &["pre", "textarea"];
+
+// script types that hold javascript, rather than e.g. JSON or templates
+const JAVASCRIPT_TYPES: &[&str] = &["text/javascript", "application/javascript"];
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attr(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+fn attr_name(attr: &Attribute) -> String {
+    match &attr.name.prefix {
+        Some(prefix) => format!("{}:{}", prefix, attr.name.local),
+        None => attr.name.local.to_string(),
+    }
+}
+
+fn is_javascript(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .find(|attr| &*attr.name.local == "type")
+        .map_or(true, |attr| {
+            JAVASCRIPT_TYPES
+                .iter()
+                .any(|ty| attr.value.eq_ignore_ascii_case(ty))
+        })
+}
+
+// the contents of a script or style element, which the parser keeps as text
+fn raw_text(children: &[Handle]) -> String {
+    let mut text = String::new();
+    for child in children {
+        if let NodeData::Text { contents } = &child.data {
+            text.push_str(&contents.borrow());
+        }
+    }
+    text
+}
+
+// the script reformatted, or as it was if it can't be parsed
+fn unminify_inline_script(text: String) -> String {
+    match parse_js("inline script".to_string(), text.clone()) {
+        Ok(script) => print_script(&script),
+        Err(_) => text,
+    }
+}
+
+fn write_node(handle: &Handle, depth: usize, out: &mut String) -> Result<()> {
+    let indent = INDENT.repeat(depth);
+    match &handle.data {
+        NodeData::Document => {
+            for child in handle.children.borrow().iter() {
+                write_node(child, depth, out)?;
+            }
+        }
+        NodeData::Doctype { name, .. } => out.push_str(&format!("{}<!DOCTYPE {}>\n", indent, name)),
+        NodeData::Text { contents } => {
+            let contents = contents.borrow();
+            let text = contents.trim();
+            if !text.is_empty() {
+                out.push_str(&format!("{}{}\n", indent, escape_text(text)));
+            }
+        }
+        NodeData::Comment { contents } => {
+            out.push_str(&format!("{}<!--{}-->\n", indent, contents));
+        }
+        NodeData::Element {
+            name,
+            attrs,
+            template_contents,
+            ..
+        } => {
+            let tag = &*name.local;
+            if PREFORMATTED_ELEMENTS.contains(&tag) {
+                let mut serialized = Vec::new();
+                let node: SerializableHandle = handle.clone().into();
+                let opts = SerializeOpts {
+                    traversal_scope: TraversalScope::IncludeNode,
+                    ..Default::default()
+                };
+                serialize(&mut serialized, &node, opts)?;
+                out.push_str(&format!("{}{}\n", indent, String::from_utf8(serialized)?));
+                return Ok(());
+            }
+            let attrs = attrs.borrow();
+            out.push_str(&format!("{}<{}", indent, tag));
+            for attr in attrs.iter() {
+                out.push_str(&format!(
+                    " {}=\"{}\"",
+                    attr_name(attr),
+                    escape_attr(&attr.value)
+                ));
+            }
+            out.push('>');
+            if VOID_ELEMENTS.contains(&tag) {
+                out.push('\n');
+                return Ok(());
+            }
+            // a template's children live in a separate document fragment
+            let children = match template_contents.borrow().as_ref() {
+                Some(contents) => contents.children.borrow().clone(),
+                None => handle.children.borrow().clone(),
+            };
+            if tag == "script" || tag == "style" {
+                let mut text = raw_text(&children);
+                if tag == "script" && is_javascript(&attrs) {
+                    text = unminify_inline_script(text);
+                }
+                let text = text.trim();
+                if !text.is_empty() {
+                    out.push('\n');
+                    for line in text.lines() {
+                        out.push_str(&format!("{}{}{}\n", indent, INDENT, line));
+                    }
+                    out.push_str(&indent);
+                }
+            } else if !children.is_empty() {
+                out.push('\n');
+                for child in &children {
+                    write_node(child, depth + 1, out)?;
+                }
+                out.push_str(&indent);
+            }
+            out.push_str(&format!("</{}>\n", tag));
+        }
+        NodeData::ProcessingInstruction { .. } => {}
+    }
+    Ok(())
+}
+
+// re-indents an HTML document, one element or run of text per line, and
+// reformats its inline javascript
+pub fn unminify_html(body: &[u8]) -> Result<Vec<u8>> {
+    let dom = parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut &body[..])?;
+    let mut out = String::new();
+    write_node(&dom.document, 0, &mut out)?;
+    Ok(out.into_bytes())
+}
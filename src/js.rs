@@ -1,7 +1,7 @@
 use anyhow::bail;
 use anyhow::Result;
 use swc::PrintArgs;
-use std::{fs::OpenOptions, io::Write, path::Path, sync::Arc};
+use std::{collections::{HashMap, HashSet}, fs::OpenOptions, io::Write, path::Path, sync::Arc};
 use swc::Compiler;
 use swc_core::{
     common::{
@@ -12,15 +12,32 @@ use swc_core::{
     },
     ecma::{
         ast::{
-            self, AssignOp, BinaryOp, BlockStmt, BlockStmtOrExpr, CallExpr, EsVersion, Expr, Ident,
-            KeyValueProp, Script
+            self, ArrowExpr, AssignOp, BinaryOp, BlockStmt, BlockStmtOrExpr, CallExpr, EsVersion,
+            Expr, Function, Ident, KeyValueProp, Lit, MemberExpr, MemberProp, ObjectLit, Pat,
+            PropName, Script, Stmt,
+        },
+        visit::{
+            as_folder, noop_visit_mut_type, noop_visit_type, FoldWith, Visit, VisitMut,
+            VisitMutWith, VisitWith,
         },
-        visit::{as_folder, noop_visit_mut_type, FoldWith, VisitMut, VisitMutWith},
     },
 };
 use swc_ecma_parser::{lexer::Lexer, Parser, StringInput, Syntax};
 
 
+// the global a chunk list is pushed onto, e.g. `self.webpackChunk` or, as
+// webpack 5 writes it, `self["webpackChunk_my_app"]`
+fn is_chunk_list_global(member: &MemberExpr) -> bool {
+    match &member.prop {
+        MemberProp::Ident(_) => true,
+        MemberProp::Computed(computed) => matches!(
+            &*computed.expr,
+            Expr::Lit(Lit::Str(name)) if name.value.contains("webpackChunk")
+        ),
+        _ => false,
+    }
+}
+
 fn verify_webpack_chunk_list(call_expr: &CallExpr) -> Option<()> {
     // we're looking for something like:
     //   `(self.webpackChunk = self.webpackChunk || []).push([ ... ])`
@@ -37,6 +54,9 @@ fn verify_webpack_chunk_list(call_expr: &CallExpr) -> Option<()> {
     }
 
     let lhs_member_expr = callee_assignment.left.as_simple()?.as_member()?;
+    if !is_chunk_list_global(lhs_member_expr) {
+        return None;
+    }
     let rhs_binary_expr = callee_assignment.right.as_bin()?;
     if rhs_binary_expr.op != BinaryOp::LogicalOr {
         return None;
@@ -113,11 +133,47 @@ impl<'a> VisitMut for WebpackChunkIdentRenameTransformer<'a> {
     }
 }
 
+// obfuscators sometimes write string literals entirely as hex escapes, e.g.
+// `"\x68\x65\x6c\x6c\x6f"`. the parser has already decoded these into the
+// literal's value, but the printer prefers the raw source text, so we drop it
+struct DecodeHexStrings;
+
+fn is_hex_escaped(raw: &str) -> bool {
+    let Some(inner) = raw.get(1..raw.len().saturating_sub(1)) else {
+        return false;
+    };
+    if inner.is_empty() || inner.len() % 4 != 0 {
+        return false;
+    }
+    inner.as_bytes().chunks(4).all(|escape| {
+        escape[0] == b'\\'
+            && escape[1] == b'x'
+            && escape[2].is_ascii_hexdigit()
+            && escape[3].is_ascii_hexdigit()
+    })
+}
+
+impl VisitMut for DecodeHexStrings {
+    noop_visit_mut_type!(); // omits TypeScript metadata
+
+    fn visit_mut_str(&mut self, string: &mut ast::Str) {
+        if string.raw.as_ref().is_some_and(|raw| is_hex_escaped(raw)) {
+            string.raw = None;
+        }
+    }
+}
+
 impl TryFrom<&KeyValueProp> for WebpackChunk {
     type Error = String;
 
     fn try_from(kv: &KeyValueProp) -> std::result::Result<Self, Self::Error> {
-        let key = kv.key.as_num().unwrap().raw.as_ref().unwrap().to_string();
+        // modules are keyed by number, or in development builds by their
+        // source path, which can't be used as a file name as-is
+        let key = match &kv.key {
+            PropName::Num(num) => num.raw.as_ref().map_or_else(|| num.value.to_string(), |raw| raw.to_string()),
+            PropName::Str(s) => s.value.replace(['/', '\\'], "_"),
+            _ => return Err(format!("unexpected chunk list key {:?}", kv.key)),
+        };
         let block: BlockStmt;
         let mut params: Vec<Ident> = Vec::new();
         match &*kv.value {
@@ -163,32 +219,210 @@ impl TryFrom<&KeyValueProp> for WebpackChunk {
     }
 }
 
-pub fn unpack_webpack_chunk_list(script: &Script) -> Option<Vec<WebpackChunk>> {
-    if script.body.len() != 1 {
-        return None;
+// webpack's runtime builds a chunk's URL in a function of the chunk id,
+// which looks up the names given by `webpackChunkName` magic comments:
+//   `(chunkId) => "static/js/" + ({"42": "myFeature"}[chunkId] || chunkId) + "." + {"42": "1a2b"}[chunkId] + ".chunk.js"`
+// the names are the object literal the id falls back from, so e.g. the
+// hashes aren't mistaken for names, and only URLs of scripts are looked at
+struct ChunkNameCollector {
+    names: HashMap<String, String>,
+}
+
+// what a function body made up of a single return statement returns
+fn returned_expr(stmts: &[Stmt]) -> Option<&Expr> {
+    match stmts {
+        [Stmt::Return(ret)] => ret.arg.as_deref(),
+        _ => None,
     }
+}
 
-    let call_expr = script.body[0].as_expr()?.expr.as_call()?;
-    verify_webpack_chunk_list(call_expr)?;
-    if call_expr.args.len() != 1 {
+// the operands of a string concatenation like `a + (b + c)`
+fn concatenated<'a>(expr: &'a Expr, operands: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::Bin(bin) if bin.op == BinaryOp::Add => {
+            concatenated(&bin.left, operands);
+            concatenated(&bin.right, operands);
+        }
+        Expr::Paren(paren) => concatenated(&paren.expr, operands),
+        expr => operands.push(expr),
+    }
+}
+
+// the names in `{...}[chunkId] || chunkId`
+fn chunk_name_lookup(expr: &Expr, chunk_id: &Ident) -> Option<HashMap<String, String>> {
+    let is_chunk_id = |expr: &Expr| expr.as_ident().is_some_and(|ident| ident.sym == chunk_id.sym);
+    let fallback = expr.as_bin().filter(|bin| bin.op == BinaryOp::LogicalOr)?;
+    let lookup = fallback.left.as_member()?;
+    if !is_chunk_id(&fallback.right) || !lookup.prop.as_computed().is_some_and(|prop| is_chunk_id(&prop.expr)) {
         return None;
     }
-    let push_arg_arr = call_expr.args[0].expr.as_array()?;
-    if push_arg_arr.elems.len() != 3 {
+    let mut names = HashMap::new();
+    for prop in &lookup.obj.as_object()?.props {
+        let kv = prop.as_prop()?.as_key_value()?;
+        let id = match &kv.key {
+            PropName::Num(num) => num.value.to_string(),
+            PropName::Str(s) => s.value.to_string(),
+            PropName::Ident(ident) => ident.sym.to_string(),
+            _ => return None,
+        };
+        let Lit::Str(name) = kv.value.as_lit()? else {
+            return None;
+        };
+        names.insert(id, name.value.to_string());
+    }
+    Some(names)
+}
+
+// the chunk names looked up in `url`, if it's a concatenation ending in `.js`
+fn chunk_url_names(url: &Expr, chunk_id: &Ident) -> Option<HashMap<String, String>> {
+    let mut operands = Vec::new();
+    concatenated(url, &mut operands);
+    let Some(Expr::Lit(Lit::Str(suffix))) = operands.last() else {
+        return None;
+    };
+    if !suffix.value.ends_with(".js") {
         return None;
     }
-    let chunk_list = push_arg_arr.elems[1].as_ref()?.expr.as_object()?;
+    operands.iter().find_map(|operand| chunk_name_lookup(operand, chunk_id))
+}
+
+impl Visit for ChunkNameCollector {
+    noop_visit_type!(); // omits TypeScript metadata
+
+    // e.g. webpack 4's `function jsonpScriptSrc(chunkId) { return ... }`
+    fn visit_function(&mut self, function: &Function) {
+        function.visit_children_with(self);
+        let [param] = function.params.as_slice() else {
+            return;
+        };
+        let (Some(chunk_id), Some(body)) = (param.pat.as_ident(), &function.body) else {
+            return;
+        };
+        if let Some(names) = returned_expr(&body.stmts).and_then(|url| chunk_url_names(url, &chunk_id.id)) {
+            self.names.extend(names);
+        }
+    }
+
+    // e.g. webpack 5's `__webpack_require__.u = (chunkId) => ...`
+    fn visit_arrow_expr(&mut self, arrow: &ArrowExpr) {
+        arrow.visit_children_with(self);
+        let [Pat::Ident(chunk_id)] = arrow.params.as_slice() else {
+            return;
+        };
+        let url = match &*arrow.body {
+            BlockStmtOrExpr::BlockStmt(block) => returned_expr(&block.stmts),
+            BlockStmtOrExpr::Expr(expr) => Some(&**expr),
+        };
+        if let Some(names) = url.and_then(|url| chunk_url_names(url, &chunk_id.id)) {
+            self.names.extend(names);
+        }
+    }
+}
+
+pub fn extract_chunk_names(script: &Script) -> HashMap<String, String> {
+    let mut collector = ChunkNameCollector {
+        names: HashMap::new(),
+    };
+    script.visit_with(&mut collector);
+    collector.names
+}
+
+// the chunk names of every webpack runtime in a HAR. a HAR can hold more
+// than one webpack app, so ids that runtimes name differently are left
+// unnamed rather than named after whichever runtime came last
+#[derive(Debug, Default)]
+pub struct ChunkNames {
+    names: HashMap<String, String>,
+    conflicting: HashSet<String>,
+}
+
+impl ChunkNames {
+    pub fn extend(&mut self, names: HashMap<String, String>) {
+        for (id, name) in names {
+            if self.conflicting.contains(&id) {
+                continue;
+            }
+            match self.names.get(&id) {
+                Some(known) if *known != name => {
+                    self.names.remove(&id);
+                    self.conflicting.insert(id);
+                }
+                _ => {
+                    self.names.insert(id, name);
+                }
+            }
+        }
+    }
+}
+
+// substitutes a chunk's numeric label with its human-readable name, if known.
+// names given to more than one chunk are suffixed with the label, so that
+// the chunks' files don't overwrite each other
+pub fn resolve_chunk_name(label: &str, chunk_names: &ChunkNames) -> String {
+    let Some(name) = chunk_names.names.get(label) else {
+        return label.to_string();
+    };
+    let file_name = name.replace(['/', '\\'], "_");
+    if chunk_names.names.values().filter(|other| *other == name).count() > 1 {
+        format!("{}_{}", file_name, label)
+    } else {
+        file_name
+    }
+}
+
+pub fn unpack_webpack_chunk_list(script: &Script) -> Option<Vec<WebpackChunk>> {
+    // only the modules are copied to decode, once the script is known to be
+    // a chunk list
+    let mut chunk_list = chunk_list_modules(script)?.clone();
+    chunk_list.visit_mut_with(&mut DecodeHexStrings);
 
     let mut result = Vec::new();
     for maybe_prop in &chunk_list.props {
         let kv = maybe_prop.as_prop()?.as_key_value()?;
-        let mut chunk = WebpackChunk::try_from(kv).unwrap();
+        let mut chunk = WebpackChunk::try_from(kv).ok()?;
         chunk.rename_webpack_bits();
         result.push(chunk);
     }
     Some(result)
 }
 
+// the object of modules pushed by a webpack chunk list, if that's all the
+// script is
+fn chunk_list_modules(script: &Script) -> Option<&ObjectLit> {
+    if script.body.len() != 1 {
+        return None;
+    }
+
+    let call_expr = script.body[0].as_expr()?.expr.as_call()?;
+    verify_webpack_chunk_list(call_expr)?;
+    if call_expr.args.len() != 1 {
+        return None;
+    }
+    // `[chunkIds, modules]`, or `[chunkIds, modules, runtime]` for chunks
+    // that also run code once loaded. the ids are usually an array, though
+    // older builds may give a single number
+    let push_arg_arr = call_expr.args[0].expr.as_array()?;
+    if !(2..=3).contains(&push_arg_arr.elems.len()) {
+        return None;
+    }
+    match &*push_arg_arr.elems[0].as_ref()?.expr {
+        Expr::Array(chunk_ids) => {
+            let is_chunk_id = |id: &Option<ast::ExprOrSpread>| {
+                matches!(
+                    id.as_ref().map(|id| &*id.expr),
+                    Some(Expr::Lit(Lit::Num(_) | Lit::Str(_)))
+                )
+            };
+            if !chunk_ids.elems.iter().all(is_chunk_id) {
+                return None;
+            }
+        }
+        Expr::Lit(Lit::Num(_)) => {}
+        _ => return None,
+    }
+    push_arg_arr.elems[1].as_ref()?.expr.as_object()
+}
+
 pub fn parse_js(file_name: String, file_text: String) -> Result<Script> {
     let cm: Lrc<SourceMap> = Default::default();
     let handler = Handler::with_tty_emitter(ColorConfig::Auto, true, false, Some(cm.clone()));
@@ -212,19 +446,196 @@ pub fn parse_js(file_name: String, file_text: String) -> Result<Script> {
     }
 }
 
-pub fn write_script(script: &Script, path: &Path) -> Result<()> {
+pub fn print_script(script: &Script) -> String {
     let c = Compiler::new(Arc::new(SourceMap::new(FilePathMapping::empty())));
     let globals = Globals::new();
     GLOBALS.set(&globals, || {
         let print_args = PrintArgs::default();
         let ast_printed = c.print(script, print_args)
             .expect("Failed to print");
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)
-            .unwrap();
-        file.write_all(ast_printed.code.as_bytes()).unwrap();
-    });
+        ast_printed.code
+    })
+}
+
+pub fn write_script(script: &Script, path: &Path) -> Result<()> {
+    let code = print_script(script);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .unwrap();
+    file.write_all(code.as_bytes()).unwrap();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack(code: &str) -> Option<Vec<WebpackChunk>> {
+        let script = parse_js("test.js".to_string(), code.to_string()).unwrap();
+        unpack_webpack_chunk_list(&script)
+    }
+
+    fn labels(chunks: &[WebpackChunk]) -> Vec<&str> {
+        chunks.iter().map(|chunk| chunk.label.as_str()).collect()
+    }
+
+    #[test]
+    fn unpacks_webpack_5_bracket_notation() {
+        let chunks = unpack(
+            r#"(self["webpackChunk_my_app"] = self["webpackChunk_my_app"] || []).push([[179], {
+                12: (e, t, n) => { n(34); },
+                34: function (e, t, n) { e.exports = 1; }
+            }]);"#,
+        )
+        .unwrap();
+        assert_eq!(labels(&chunks), ["12", "34"]);
+    }
+
+    #[test]
+    fn unpacks_dotted_chunk_list_global() {
+        let chunks = unpack(
+            "(self.webpackChunkmy_app = self.webpackChunkmy_app || []).push([[1], {
+                5: function (e, t, n) {}
+            }]);",
+        )
+        .unwrap();
+        assert_eq!(labels(&chunks), ["5"]);
+    }
+
+    #[test]
+    fn unpacks_array_and_single_number_chunk_ids() {
+        for ids in ["[179]", "[179, 180]", "[\"vendors\", 3]", "[]", "179"] {
+            let code = format!(
+                "(self.webpackChunk = self.webpackChunk || []).push([{}, {{ 7: function (e) {{}} }}]);",
+                ids
+            );
+            let chunks = unpack(&code).unwrap_or_else(|| panic!("chunk ids {}", ids));
+            assert_eq!(labels(&chunks), ["7"], "chunk ids {}", ids);
+        }
+        // chunk ids are always literals
+        assert!(unpack(
+            "(self.webpackChunk = self.webpackChunk || []).push([[a], { 7: function (e) {} }]);"
+        )
+        .is_none());
+        assert!(unpack(
+            "(self.webpackChunk = self.webpackChunk || []).push([\"179\", { 7: function (e) {} }]);"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn unpacks_chunks_with_a_runtime() {
+        let chunks = unpack(
+            r#"(self["webpackChunk_app"] = self["webpackChunk_app"] || []).push([[792], {
+                1: (e, t, n) => {}
+            }, (e) => { e.O(0, [792], () => e(1)); }]);"#,
+        )
+        .unwrap();
+        assert_eq!(labels(&chunks), ["1"]);
+    }
+
+    #[test]
+    fn names_development_modules_by_path() {
+        let chunks = unpack(
+            r#"(self["webpackChunk_app"] = self["webpackChunk_app"] || []).push([["main"], {
+                "./src/index.js": (module, exports, require) => {}
+            }]);"#,
+        )
+        .unwrap();
+        assert_eq!(labels(&chunks), ["._src_index.js"]);
+    }
+
+    #[test]
+    fn ignores_other_pushes() {
+        // a bracket-notation global that isn't a chunk list
+        assert!(unpack(r#"(self["dataLayer"] = self["dataLayer"] || []).push([[1], { 2: function () {} }]);"#).is_none());
+        // different globals on either side of the ||
+        assert!(unpack(r#"(self["webpackChunk_a"] = self["webpackChunk_b"] || []).push([[1], { 2: function () {} }]);"#).is_none());
+        assert!(unpack("(self.webpackChunk = self.webpackChunk || []).push([[1]]);").is_none());
+        assert!(unpack("window.dataLayer.push({ event: \"load\" });").is_none());
+    }
+
+    #[test]
+    fn renames_module_parameters() {
+        let chunks = unpack(
+            "(self.webpackChunk = self.webpackChunk || []).push([[1], {
+                2: function (e, t, n) { e.exports = n(3); t.x = 1; }
+            }]);",
+        )
+        .unwrap();
+        let code = print_script(&chunks.into_iter().next().unwrap().into_script());
+        assert!(code.contains("module.exports = __webpack_require__(3)"), "{}", code);
+        assert!(code.contains("exports.x = 1"), "{}", code);
+    }
+
+    fn chunk_names(code: &str) -> HashMap<String, String> {
+        extract_chunk_names(&parse_js("test.js".to_string(), code.to_string()).unwrap())
+    }
+
+    fn names(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(id, name)| (id.to_string(), name.to_string())).collect()
+    }
+
+    #[test]
+    fn extracts_names_from_chunk_url_function() {
+        // webpack 5, minified
+        assert_eq!(
+            chunk_names(r#"n.u = e => "static/js/" + ({42: "myFeature", 7: "admin/panel"}[e] || e) + "." + {7: "9f8e", 42: "1a2b"}[e] + ".chunk.js";"#),
+            names(&[("42", "myFeature"), ("7", "admin/panel")])
+        );
+        // webpack 4
+        assert_eq!(
+            chunk_names(r#"function jsonpScriptSrc(chunkId) {
+                return __webpack_require__.p + "" + ({"vendors~main": "vendors"}[chunkId] || chunkId) + ".js";
+            }"#),
+            names(&[("vendors~main", "vendors")])
+        );
+    }
+
+    #[test]
+    fn ignores_other_lookups() {
+        // not the URL of a script
+        assert!(chunk_names(r#"n.miniCssF = e => "static/css/" + ({42: "myFeature"}[e] || e) + ".css";"#).is_empty());
+        // not concatenated into a URL
+        assert!(chunk_names(r#"var label = e => ({1: "one", 2: "two"}[e] || e);"#).is_empty());
+        assert!(chunk_names(r#"var t = {1: "one"}[e]; load(t + ".js");"#).is_empty());
+        // doesn't fall back to the chunk id
+        assert!(chunk_names(r#"n.u = e => "static/js/" + {42: "1a2b"}[e] + ".js";"#).is_empty());
+        assert!(chunk_names(r#"n.u = e => "static/js/" + ({42: "a"}[e] || x) + ".js";"#).is_empty());
+    }
+
+    #[test]
+    fn leaves_conflicting_chunk_ids_unnamed() {
+        let mut chunk_names = ChunkNames::default();
+        chunk_names.extend(names(&[("1", "home"), ("2", "settings")]));
+        chunk_names.extend(names(&[("1", "checkout"), ("2", "settings")]));
+        chunk_names.extend(names(&[("1", "home")]));
+        assert_eq!(resolve_chunk_name("1", &chunk_names), "1");
+        assert_eq!(resolve_chunk_name("2", &chunk_names), "settings");
+        assert_eq!(resolve_chunk_name("3", &chunk_names), "3");
+    }
+
+    #[test]
+    fn disambiguates_shared_chunk_names() {
+        let mut chunk_names = ChunkNames::default();
+        chunk_names.extend(names(&[("1", "lazy"), ("2", "lazy"), ("3", "admin/panel")]));
+        assert_eq!(resolve_chunk_name("1", &chunk_names), "lazy_1");
+        assert_eq!(resolve_chunk_name("2", &chunk_names), "lazy_2");
+        assert_eq!(resolve_chunk_name("3", &chunk_names), "admin_panel");
+    }
+
+    #[test]
+    fn decodes_hex_escaped_module_strings() {
+        let chunks = unpack(
+            r#"(self.webpackChunk = self.webpackChunk || []).push([[1], {
+                2: function (e) { e.exports = "\x68\x69"; }
+            }]);"#,
+        )
+        .unwrap();
+        let code = print_script(&chunks.into_iter().next().unwrap().into_script());
+        assert!(code.contains("hi") && !code.contains(r"\x68"), "{}", code);
+    }
+}
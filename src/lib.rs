@@ -0,0 +1,82 @@
+//! Serve, dump and inspect HAR files.
+//!
+//! The `harbinger` binary is built on this library, which can also be used
+//! directly, e.g. to serve a HAR from an integration test. Rocket's local
+//! client dispatches requests to the server without binding a port:
+//!
+//! ```
+//! use harbinger::{build_server, Har, ServerOptions};
+//! use rocket::http::Status;
+//! use rocket::local::asynchronous::Client;
+//!
+//! # rocket::async_test(async {
+//! let har = Har::from_json_value(serde_json::json!({
+//!     "log": {
+//!         "version": "1.2",
+//!         "creator": { "name": "example", "version": "1.0" },
+//!         "entries": [{
+//!             "startedDateTime": "2023-06-01T12:00:00.000Z",
+//!             "time": 12.0,
+//!             "request": {
+//!                 "method": "GET",
+//!                 "url": "https://example.com/hello",
+//!                 "httpVersion": "HTTP/1.1",
+//!                 "cookies": [],
+//!                 "headers": [],
+//!                 "queryString": [],
+//!                 "headersSize": -1,
+//!                 "bodySize": 0
+//!             },
+//!             "response": {
+//!                 "status": 200,
+//!                 "statusText": "OK",
+//!                 "httpVersion": "HTTP/1.1",
+//!                 "cookies": [],
+//!                 "headers": [{ "name": "Content-Type", "value": "text/plain" }],
+//!                 "content": { "size": 5, "mimeType": "text/plain", "text": "hello" },
+//!                 "redirectURL": "",
+//!                 "headersSize": -1,
+//!                 "bodySize": 5
+//!             },
+//!             "cache": {},
+//!             "timings": { "send": 0.0, "wait": 12.0, "receive": 0.0 }
+//!         }]
+//!     }
+//! }))
+//! .unwrap();
+//!
+//! let server = build_server(&har, &ServerOptions::default()).unwrap();
+//! let client = Client::tracked(server).await.unwrap();
+//! // entries on the HAR's origin are served at their path
+//! let response = client.get("/hello").dispatch().await;
+//! assert_eq!(response.status(), Status::Ok);
+//! assert_eq!(response.into_string().await.as_deref(), Some("hello"));
+//! # });
+//! ```
+
+pub mod blackhole;
+#[cfg(feature = "cli")]
+pub mod config;
+pub mod cookies;
+pub mod diff;
+pub mod dump;
+pub mod error;
+pub mod export;
+#[cfg(feature = "cli")]
+pub mod guide;
+pub mod har;
+pub mod html;
+pub mod js;
+pub mod recorder;
+pub mod search;
+pub mod server;
+pub mod stats;
+pub mod validate;
+pub mod verify;
+
+pub use crate::blackhole::build_blackhole;
+pub use crate::dump::{dump, DumpOptions, DumpSummary};
+pub use crate::error::HarbingerError;
+pub use crate::har::{DedupStrategy, Entry, Har};
+pub use crate::js::{parse_js, unpack_webpack_chunk_list, write_script, WebpackChunk};
+pub use crate::server::{build_server, ServerOptions};
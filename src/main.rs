@@ -1,91 +1,1126 @@
-mod blackhole;
-mod dump;
-mod error;
-mod guide;
-mod har;
-mod js;
-mod server;
-
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueHint};
+use clap_complete::Shell;
+use glob::Pattern;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use regex::Regex;
+use rocket::config::LogLevel;
+use rocket::futures::future::join_all;
+use rocket::Shutdown;
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::join;
+use tokio::time::sleep;
+use tracing_subscriber::{filter::EnvFilter, fmt::format::FmtSpan};
 
-use crate::blackhole::build_blackhole;
-use crate::dump::dump;
-use crate::har::Har;
-use crate::server::build_server;
+use harbinger::blackhole::{
+    build_blackhole_from_config, build_https_blackhole_from_config, BlackholeConfig, BoundPort,
+};
+use harbinger::config::{ConfigFile, DEFAULT_CONFIG_PATH};
+use harbinger::diff::{DiffFormat, HarDiff};
+use harbinger::dump::{dump, DumpOptions};
+use harbinger::export::apply_overrides;
+use harbinger::har::{DedupStrategy, EntrySort, Har, MergeStrategy, PartyScope, ReadOptions};
+use harbinger::search::{print_results, search, SearchFormat, SearchQuery, UrlPattern};
+use harbinger::server::{
+    build_server, dry_run, parse_delay_rule, parse_header, parse_latency_scale, print_routes,
+    AccessLogFormat, EntryTable, RequestLogFormat, ServerOptions, TlsSource, DEFAULT_PORT,
+    DEFAULT_REQUEST_BODY_LIMIT, DEFAULT_SHUTDOWN_TIMEOUT, DEFAULT_TLS_PORT,
+};
+use harbinger::stats::{print_party_summary, print_waterfall, HarSummary, StatsFormat};
+use harbinger::validate::validate;
+use harbinger::verify::verify;
 
 #[derive(Parser, Debug)]
 struct Args {
     #[command(subcommand)]
     command: Command,
+
+    /// Set the log level for a module, e.g. `harbinger::server=debug` (repeatable)
+    #[arg(long, global = true, value_name = "MODULE=LEVEL")]
+    log_level: Vec<String>,
+
+    /// Only log warnings from harbinger and errors from rocket
+    #[arg(long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log debug output from both harbinger and rocket
+    #[arg(long, global = true)]
+    verbose: bool,
+
+    /// Log non-fatal problems with a HAR's structure, like mismatched page
+    /// references, at debug level instead of as warnings
+    #[arg(long, global = true)]
+    suppress_warnings: bool,
+
+    /// Stop after SECS seconds, exiting with an error. serve shuts down
+    /// gracefully, and dump finishes the entry it's on
+    #[arg(long, global = true, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// Read any options not given on the command line from this TOML file
+    /// (defaults to ./harbinger.toml, if it exists)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+}
+
+impl Args {
+    // parses the command line, filling in options it doesn't give from the
+    // config file, if there is one
+    fn parse_with_config() -> Self {
+        let cli: Vec<OsString> = std::env::args_os().collect();
+        let command = Args::command();
+        let matches = command.clone().get_matches_from(cli.clone());
+        let config_path = match matches.get_one::<PathBuf>("config") {
+            Some(path) => path.clone(),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => PathBuf::from(DEFAULT_CONFIG_PATH),
+            None => return Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()),
+        };
+        let cli = ConfigFile::read(&config_path)
+            .and_then(|config| config.apply(&command, &matches, cli))
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load config {}: {}", config_path.display(), e);
+                std::process::exit(2);
+            });
+        Args::parse_from(cli)
+    }
+
+    // tracing filter directives, starting from RUST_LOG if it's set, or None
+    // to leave logging to rocket
+    fn log_filters(&self) -> Option<String> {
+        let mut directives: Vec<String> = std::env::var("RUST_LOG").ok().into_iter().collect();
+        if self.quiet {
+            directives.push("harbinger=warn,rocket=error".to_string());
+        }
+        if self.verbose {
+            directives.push("harbinger=debug,rocket=debug".to_string());
+        }
+        directives.extend(self.log_level.iter().cloned());
+        (!directives.is_empty()).then(|| directives.join(","))
+    }
+
+    // rocket filters its own logging by a single level, taken from the last
+    // directive for the `rocket` target (or `_`, which rocket logs much of
+    // its output under), or else from the last global level
+    fn rocket_log_level(&self) -> LogLevel {
+        let mut target_level = None;
+        let mut global_level = None;
+        let filters = self.log_filters().unwrap_or_default();
+        for directive in filters.split(',').map(str::trim) {
+            let is_rocket = |target: &str| target == "rocket" || target == "_";
+            match directive.split_once('=') {
+                Some((target, level)) if is_rocket(target) => {
+                    target_level = rocket_level(level).or(target_level);
+                }
+                Some(_) => {}
+                // a bare target enables all of its levels
+                None if is_rocket(directive) => target_level = Some(LogLevel::Debug),
+                None => global_level = rocket_level(directive).or(global_level),
+            }
+        }
+        target_level.or(global_level).unwrap_or(LogLevel::Debug)
+    }
+}
+
+// the rocket log level closest to a tracing level
+fn rocket_level(level: &str) -> Option<LogLevel> {
+    match level.trim().to_ascii_lowercase().as_str() {
+        "off" => Some(LogLevel::Off),
+        "error" | "warn" => Some(LogLevel::Critical),
+        "info" => Some(LogLevel::Normal),
+        "debug" | "trace" => Some(LogLevel::Debug),
+        _ => None,
+    }
 }
 
 #[derive(Subcommand, Debug)]
 enum Command {
     Serve {
+        #[arg(value_hint = ValueHint::FilePath)]
         har_path: PathBuf,
 
-        #[arg(long, short)]
+        /// Only load the entries of the HAR page with this id (defaults to
+        /// every entry, with the first page as the primary page)
+        #[arg(long, value_name = "ID")]
+        page: Option<String>,
+
+        #[arg(long, short, value_hint = ValueHint::DirPath)]
         dump_path: Option<PathBuf>,
 
-        #[arg(long, short, default_value_t = 8000)]
-        port: u16,
+        /// Keep only one entry for each method, URL and request body, rather
+        /// than serving every recorded copy. With --verbose, the URLs of the
+        /// dropped entries are listed
+        #[arg(long, value_enum, value_name = "STRATEGY")]
+        dedup: Option<DedupStrategy>,
+
+        /// Reload the HAR's entries whenever the file changes, without
+        /// restarting the server. Requests already being answered finish
+        /// with the old entries
+        #[arg(long)]
+        watch: bool,
+
+        /// On Ctrl-C or SIGTERM, wait up to this many seconds for requests
+        /// being handled to finish before closing their connections. The
+        /// number of requests reported as pending leaves out responses whose
+        /// bodies are still streaming (e.g. server-sent events) and upgraded
+        /// websockets, though their connections are waited for all the same
+        #[arg(long, value_name = "SECS", default_value_t = DEFAULT_SHUTDOWN_TIMEOUT.as_secs())]
+        shutdown_timeout: u64,
+
+        /// Serve files from this directory instead of the matching entries'
+        /// bodies, checked before --dump-path. Files are laid out as `dump`
+        /// would write them, but only the ones to override need to exist
+        #[arg(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
+        override_dir: Option<PathBuf>,
+
+        /// Port to serve on, or 0 to have the OS assign one and print it
+        /// (defaults to 8000, or 8443 with TLS)
+        #[arg(long, short)]
+        port: Option<u16>,
+
+        /// Serve over HTTPS with a generated self-signed certificate, which
+        /// is written to harbinger_ca.pem
+        #[arg(long)]
+        tls: bool,
+
+        /// Serve over HTTPS with this PEM certificate (chain) rather than a
+        /// generated one
+        #[arg(long, value_name = "PATH", requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+
+        /// The PEM private key for --tls-cert
+        #[arg(long, value_name = "PATH", requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
 
         #[arg(long)]
         proxy: Option<reqwest::Url>,
 
+        /// Only proxy requests whose path matches this regex (repeatable,
+        /// matching any of them)
+        #[arg(long, value_name = "REGEX", value_parser = Regex::new, requires = "proxy")]
+        proxy_pattern: Vec<Regex>,
+
+        /// Don't send this request header to the proxy (repeatable).
+        /// hop-by-hop headers like Connection are never sent
+        #[arg(long, value_name = "NAME", requires = "proxy")]
+        proxy_strip_header: Vec<String>,
+
         #[arg(long)]
         blackhole_port: Option<u16>,
+
+        /// Load the blackhole's settings from a TOML file. --blackhole-port
+        /// overrides the port it configures
+        #[arg(long)]
+        blackhole_config: Option<PathBuf>,
+
+        /// Also run the blackhole over HTTPS on this port, with a
+        /// self-signed certificate, for requests to https:// URLs
+        #[arg(long, value_name = "PORT")]
+        blackhole_https_port: Option<u16>,
+
+        /// Print every incoming request and how it was matched
+        #[arg(long)]
+        log_requests: bool,
+
+        #[arg(long, value_enum, default_value_t = RequestLogFormat::Text)]
+        log_format: RequestLogFormat,
+
+        /// Append a line for every request to this file, with its time,
+        /// method, URI, whether it matched an entry, status, response size,
+        /// and latency
+        #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        access_log: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = AccessLogFormat::Jsonl)]
+        access_log_format: AccessLogFormat,
+
+        /// Delay responses whose URL matches a glob, e.g. `*/api/*=500`
+        #[arg(long, value_name = "URL_PATTERN=MS", value_parser = parse_delay_rule)]
+        response_delay: Vec<(Pattern, Duration)>,
+
+        /// Add up to the given random delay to responses whose URL matches a glob
+        #[arg(long, value_name = "URL_PATTERN=MS", value_parser = parse_delay_rule)]
+        response_delay_jitter: Vec<(Pattern, Duration)>,
+
+        /// Delay each response by the server wait time recorded in the HAR
+        #[arg(long)]
+        simulate_latency: bool,
+
+        /// Multiply recorded wait times by this when simulating latency
+        /// (defaults to 1.0)
+        #[arg(
+            long,
+            value_name = "SCALE",
+            value_parser = parse_latency_scale,
+            requires = "simulate_latency"
+        )]
+        latency_scale: Option<f64>,
+
+        /// Wait this long between the events of recorded text/event-stream
+        /// responses (defaults to spreading them over the time the response
+        /// took to receive, or 100ms apart if that wasn't recorded)
+        #[arg(long, value_name = "MS")]
+        sse_delay_ms: Option<u64>,
+
+        /// Always serve the first entry matching a request, rather than
+        /// cycling through every matching entry on successive requests
+        #[arg(long)]
+        no_round_robin: bool,
+
+        /// Maximum request body size, in bytes, read when matching entries by body
+        #[arg(long, default_value_t = DEFAULT_REQUEST_BODY_LIMIT)]
+        request_body_limit: u64,
+
+        /// List the routes that would be registered, then exit
+        #[arg(long)]
+        print_routes: bool,
+
+        /// Print machine-readable JSON output
+        #[arg(long)]
+        json: bool,
+
+        /// Check that the HAR can be served, report any issues, then exit
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Serve the HAR's origin as if it were hosted at this URL, rewriting
+        /// references to the original origin in response bodies
+        #[arg(long)]
+        base_url: Option<reqwest::Url>,
+
+        /// Leave redirect Location headers as recorded, rather than rewriting
+        /// them for the service worker
+        #[arg(long)]
+        no_rewrite_location: bool,
+
+        /// Serve this file at /harbinger instead of the built-in UI
+        #[arg(long)]
+        serve_index: Option<PathBuf>,
+
+        /// Serve this file instead of the built-in harbinger_app.js
+        #[arg(long)]
+        serve_app_js: Option<PathBuf>,
+
+        /// Serve this file instead of the built-in harbinger_worker.js
+        #[arg(long)]
+        serve_worker_js: Option<PathBuf>,
+
+        /// Respond with 503 instead of serving bodies larger than this many bytes
+        #[arg(long, value_name = "BYTES")]
+        max_response_size: Option<u64>,
+
+        /// Respond with 503 if a proxied response body is larger than this many bytes
+        #[arg(long, value_name = "BYTES")]
+        max_proxy_response_size: Option<u64>,
+
+        /// Add this header to every HAR and proxied response, replacing any
+        /// recorded header with the same name, e.g. `X-Harbinger: 1`
+        #[arg(long, value_name = "NAME:VALUE", value_parser = parse_header)]
+        inject_header: Vec<(String, String)>,
+
+        /// Additional response headers to strip from HAR responses
+        #[arg(long, value_delimiter = ',', value_name = "NAME")]
+        strip_response_headers: Vec<String>,
+
+        /// Strip Set-Cookie from HAR responses. Replaying a logged-in
+        /// session usually needs its cookies, so they're sent by default
+        #[arg(long)]
+        strip_cookies: bool,
+
+        /// Rewrite Set-Cookie headers so browsers accept them from
+        /// harbinger: drop their Domain and Secure attributes, and turn
+        /// SameSite=None into SameSite=Lax
+        #[arg(long)]
+        rewrite_cookies: bool,
+
+        /// Remember cookies set by responses, and send them with proxied
+        /// requests whose path they match. The jar is loaded from this file
+        /// at startup and saved back to it on shutdown
+        #[arg(long, value_name = "PATH")]
+        cookie_jar: Option<PathBuf>,
+
+        /// Record requests forwarded to --proxy as new entries, appending them
+        /// to this HAR file when the server shuts down
+        #[arg(long, value_name = "OUTPUT_HAR", requires = "proxy")]
+        record_new: Option<PathBuf>,
+
+        /// Serve only the HAR's entries, each at its original path, without
+        /// the harbinger UI and service worker
+        #[arg(long, conflicts_with_all = ["serve_index", "serve_app_js", "serve_worker_js"])]
+        no_service_worker: bool,
+
+        /// Respond with 503 to requests for HAR entries while N are already
+        /// being handled
+        #[arg(long, value_name = "N")]
+        concurrent_limit: Option<NonZeroUsize>,
+
+        /// Send this Content-Security-Policy with HAR responses instead of
+        /// harbinger's permissive default
+        #[arg(long, value_name = "POLICY", conflicts_with = "no_csp")]
+        csp_override: Option<String>,
+
+        /// Don't send a Content-Security-Policy with HAR responses
+        #[arg(long)]
+        no_csp: bool,
+
+        /// Send the CSP as Content-Security-Policy-Report-Only, logging
+        /// violations reported to /harbinger/csp-report instead of blocking
+        /// them
+        #[arg(long, conflicts_with = "no_csp")]
+        csp_report_only: bool,
+
+        /// Add Access-Control-Allow-Origin with this origin to every
+        /// response, and answer CORS preflight requests
+        #[arg(long, value_name = "ORIGIN")]
+        cors_origin: Option<String>,
+
+        /// Also add Access-Control-Allow-Credentials: true to every response
+        #[arg(long, requires = "cors_origin")]
+        cors_credentials: bool,
+
+        /// Replace the recorded Cache-Control of HAR responses with no-store.
+        /// Recorded max-age values have usually long expired, or may keep
+        /// the browser from seeing edits to dumped files, but with no-store
+        /// every reload downloads everything again instead of revalidating
+        #[arg(long, conflicts_with = "preserve_cache_control")]
+        reset_cache_control: bool,
+
+        /// Send HAR responses with their recorded Cache-Control (the
+        /// default). Reloads behave as they did when recording, though
+        /// responses the browser has cached may be reused without asking
+        /// harbinger
+        #[arg(long)]
+        preserve_cache_control: bool,
     },
     Dump {
+        #[arg(value_hint = ValueHint::FilePath)]
         har_path: PathBuf,
 
+        /// Only load the entries of the HAR page with this id (defaults to
+        /// every entry, with the first page as the primary page)
+        #[arg(long, value_name = "ID")]
+        page: Option<String>,
+
         #[arg(long)]
         raw: bool,
 
-        #[arg(long, short)]
+        /// Write every file directly into the output directory, named after
+        /// its URL, with a _manifest.json mapping files back to URLs
+        #[arg(long)]
+        flatten: bool,
+
+        /// Continue a dump into an existing output directory, skipping files
+        /// its _manifest.json records as already written
+        #[arg(long)]
+        resume: bool,
+
+        /// With --resume, rewrite files even if they were already written
+        #[arg(long, requires = "resume")]
+        force: bool,
+
+        /// Dump into an existing output directory, skipping files that
+        /// already exist there
+        #[arg(long)]
+        incremental: bool,
+
+        /// With --incremental, overwrite files that already exist
+        #[arg(long, requires = "incremental")]
+        overwrite: bool,
+
+        /// Only dump (and unminify) javascript, skipping source maps
+        #[arg(long, conflicts_with = "raw")]
+        only_javascript: bool,
+
+        /// Indent JSON responses
+        #[arg(long, conflicts_with = "raw")]
+        pretty_json: bool,
+
+        /// Indent CSS responses
+        #[arg(long, conflicts_with = "raw")]
+        unminify_css: bool,
+
+        /// Indent HTML responses, and unminify their inline javascript
+        #[arg(long, conflicts_with = "raw")]
+        unminify_html: bool,
+
+        #[arg(long, short, value_hint = ValueHint::DirPath)]
         output_path: PathBuf,
+
+        /// Only dump entries with these methods (defaults to all methods)
+        #[arg(long, value_delimiter = ',')]
+        include_methods: Option<Vec<String>>,
+
+        /// Skip entries with these methods
+        #[arg(long, value_delimiter = ',')]
+        exclude_methods: Vec<String>,
+
+        /// Skip the first M entries of the HAR
+        #[arg(long, value_name = "M", default_value_t = 0)]
+        entry_offset: usize,
+
+        /// Dump at most N entries
+        #[arg(long, value_name = "N")]
+        entry_limit: Option<usize>,
+
+        /// Number of entries to process at once (defaults to one per core)
+        #[arg(long, short, value_name = "N", default_value_t = 0)]
+        jobs: usize,
+
+        /// Don't show a progress bar (it's also hidden when stdout isn't a
+        /// terminal)
+        #[arg(long)]
+        no_progress: bool,
+
+        /// Dump entries sorted by URL, by response size (largest first), or
+        /// grouped by MIME type, rather than in recording order
+        #[arg(long, value_enum, value_name = "FIELD")]
+        entry_sort: Option<EntrySort>,
     },
     Guide,
+    /// Check that a HAR can be served, without starting the server. Exits
+    /// with 1 if any entry can't be served
+    Validate {
+        #[arg(value_hint = ValueHint::FilePath)]
+        har_path: PathBuf,
+    },
+    /// Check a dump directory against the HAR it was dumped from
+    Verify {
+        #[arg(value_hint = ValueHint::FilePath)]
+        har_path: PathBuf,
+
+        #[arg(value_hint = ValueHint::DirPath)]
+        dump_path: PathBuf,
+    },
+    /// Show which entries were added, deleted or modified between two HARs,
+    /// and how the modified ones changed
+    Diff {
+        #[arg(value_hint = ValueHint::FilePath)]
+        base: PathBuf,
+
+        #[arg(value_hint = ValueHint::FilePath)]
+        updated: PathBuf,
+
+        /// Number of unchanged lines to show around each change
+        #[arg(long, value_name = "N", default_value_t = 3)]
+        context: usize,
+
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+    },
+    /// Write a copy of a HAR with the response bodies `serve --dump-path`
+    /// would serve from a dump directory, so that edits to dumped files can
+    /// be shared as a single HAR
+    Export {
+        #[arg(value_hint = ValueHint::FilePath)]
+        har_path: PathBuf,
+
+        #[arg(value_hint = ValueHint::DirPath)]
+        dump_path: PathBuf,
+
+        /// Where to write the new HAR
+        #[arg(long, short, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+    },
+    /// Combine several HARs into one, with entries in the order the HARs
+    /// are given
+    Merge {
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        inputs: Vec<PathBuf>,
+
+        /// Where to write the merged HAR
+        #[arg(long, short, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Which entries to keep when more than one HAR has an entry for the
+        /// same method and URL
+        #[arg(long, value_enum, default_value_t = MergeStrategy::All)]
+        strategy: MergeStrategy,
+    },
+    /// Summarize what a HAR contains, and print a waterfall of when each
+    /// request in it started
+    Stats {
+        #[arg(value_hint = ValueHint::FilePath)]
+        har_path: PathBuf,
+
+        /// Print the summary as readable text, or as JSON without the
+        /// party breakdown or waterfall
+        #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+        format: StatsFormat,
+
+        /// Treat only the origin host (host) or also its sibling subdomains
+        /// (site) as first-party
+        #[arg(long, value_enum, default_value_t = PartyScope::Host)]
+        party_scope: PartyScope,
+
+        /// List waterfall rows sorted by URL, by response size (largest
+        /// first), or grouped by MIME type, rather than by start time
+        #[arg(long, value_enum, value_name = "FIELD")]
+        entry_sort: Option<EntrySort>,
+    },
+    /// List the entries whose URL matches a pattern, with their method,
+    /// status, content type and response body size
+    Search {
+        #[arg(value_hint = ValueHint::FilePath)]
+        har_path: PathBuf,
+
+        /// A glob matching the whole URL, e.g. `*/api/*`, or with --regex, a
+        /// regex matching anywhere in it
+        pattern: String,
+
+        /// Treat the pattern as a regex rather than a glob
+        #[arg(long)]
+        regex: bool,
+
+        /// Only list entries with this method
+        #[arg(long)]
+        method: Option<String>,
+
+        /// Only list entries with this response status
+        #[arg(long)]
+        status: Option<u16>,
+
+        /// Print the entries as readable text, or as a JSON array
+        #[arg(long, value_enum, default_value_t = SearchFormat::Text)]
+        format: SearchFormat,
+
+        /// Also print the first N bytes of each response body (200 if N
+        /// isn't given)
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "200")]
+        body: Option<usize>,
+    },
+    /// Print a completion script for a shell, e.g.
+    /// `harbinger completions bash > /etc/bash_completion.d/harbinger`
+    #[command(hide = true)]
+    Completions {
+        shell: Shell,
+    },
+}
+
+// reads the HAR to serve, keeping one entry per request if `dedup` is given,
+// and listing the dropped entries if `verbose`
+fn read_served_har(
+    path: &Path,
+    options: ReadOptions,
+    dedup: Option<DedupStrategy>,
+    verbose: bool,
+) -> anyhow::Result<Har> {
+    let mut har = Har::read_with(path, options)?;
+    if let Some(strategy) = dedup {
+        let dropped = har.dedup(strategy);
+        if verbose && !dropped.is_empty() {
+            eprintln!("warning: --dedup dropped {} entries:", dropped.len());
+            for entry in &dropped {
+                eprintln!("  #{} {}", entry.index(), entry);
+            }
+        }
+    }
+    Ok(har)
+}
+
+// calls `reload` whenever the HAR at `har_path` is written. its directory is
+// watched rather than the file itself, since editors often save by
+// replacing the file
+fn watch_har(
+    har_path: &Path,
+    mut reload: impl FnMut() -> anyhow::Result<()> + Send + 'static,
+) -> notify::Result<RecommendedWatcher> {
+    let file_name = har_path.file_name().map(OsString::from);
+    let dir = match har_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let is_har = event
+            .paths
+            .iter()
+            .any(|path| path.file_name() == file_name.as_deref());
+        if is_har && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            // a HAR caught halfway through being written fails to parse,
+            // and the next write event reloads it
+            if let Err(e) = reload() {
+                eprintln!("failed to reload HAR: {}", e);
+            }
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+// gracefully shuts down each server once `timeout` has passed
+fn shutdown_after(timeout: Duration, servers: Vec<Shutdown>) {
+    tokio::spawn(async move {
+        sleep(timeout).await;
+        eprintln!("timed out after {}s, shutting down", timeout.as_secs());
+        for server in servers {
+            server.notify();
+        }
+    });
 }
 
 #[rocket::main]
 async fn main() {
-    let args = Args::parse();
+    let args = Args::parse_with_config();
+    if let Some(filters) = args.log_filters() {
+        // log records from harbinger and rocket are forwarded to the same
+        // subscriber. span timings are printed when each span closes
+        tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::new(filters))
+            .with_span_events(FmtSpan::CLOSE)
+            .init();
+    }
+    let read_options = ReadOptions {
+        suppress_warnings: args.suppress_warnings,
+        ..Default::default()
+    };
+    let timeout = args.timeout.map(Duration::from_secs);
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let timed_out = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    // serve and dump stop themselves, so they can clean up first
+    if let Some(timeout) = timeout {
+        if !matches!(args.command, Command::Serve { .. } | Command::Dump { .. }) {
+            tokio::spawn(async move {
+                sleep(timeout).await;
+                eprintln!("timed out after {}s", timeout.as_secs());
+                std::process::exit(1);
+            });
+        }
+    }
     match &args.command {
         Command::Serve {
             har_path,
+            page,
             dump_path,
+            dedup,
+            watch,
+            shutdown_timeout,
+            override_dir,
             port,
             proxy,
+            proxy_pattern,
+            proxy_strip_header,
             blackhole_port,
-            ..
+            blackhole_config,
+            blackhole_https_port,
+            log_requests,
+            log_format,
+            access_log,
+            access_log_format,
+            response_delay,
+            response_delay_jitter,
+            simulate_latency,
+            latency_scale,
+            sse_delay_ms,
+            no_round_robin,
+            request_body_limit,
+            print_routes: should_print_routes,
+            json,
+            dry_run: is_dry_run,
+            base_url,
+            no_rewrite_location,
+            serve_index,
+            serve_app_js,
+            serve_worker_js,
+            max_response_size,
+            max_proxy_response_size,
+            inject_header,
+            strip_response_headers,
+            strip_cookies,
+            rewrite_cookies,
+            cookie_jar,
+            record_new,
+            no_service_worker,
+            concurrent_limit,
+            csp_override,
+            no_csp,
+            csp_report_only,
+            cors_origin,
+            cors_credentials,
+            reset_cache_control,
+            preserve_cache_control: _,
+            tls,
+            tls_cert,
+            tls_key,
         } => {
-            let har = Har::read(har_path).unwrap();
-            let harbinger_server = build_server(&har, *port, dump_path.as_ref(), proxy.as_ref())
-                .expect("failed to initialize server from HAR");
-            if let Some(port) = blackhole_port {
-                let blackhole = build_blackhole(*port);
-                let _ = join!(harbinger_server.launch(), blackhole.launch());
+            let page_options = ReadOptions {
+                page_id: page.as_deref(),
+                ..read_options
+            };
+            let har = read_served_har(har_path, page_options, *dedup, args.verbose).unwrap();
+            let tls_source = match (tls_cert, tls_key) {
+                (Some(cert), Some(key)) => Some(TlsSource::Files {
+                    cert: cert.clone(),
+                    key: key.clone(),
+                }),
+                _ if *tls => Some(TlsSource::SelfSigned),
+                _ => None,
+            };
+            let options = ServerOptions {
+                port: port.unwrap_or(if tls_source.is_some() {
+                    DEFAULT_TLS_PORT
+                } else {
+                    DEFAULT_PORT
+                }),
+                dump_path: dump_path.clone(),
+                override_dir: override_dir.clone(),
+                proxy: proxy.clone(),
+                proxy_patterns: proxy_pattern.clone(),
+                proxy_strip_headers: proxy_strip_header.clone(),
+                log_requests: log_requests.then_some(*log_format),
+                access_log: access_log.clone().map(|path| (path, *access_log_format)),
+                response_delays: response_delay.clone(),
+                response_delay_jitter: response_delay_jitter.clone(),
+                request_body_limit: *request_body_limit,
+                log_level: args.rocket_log_level(),
+                base_url: base_url.clone(),
+                rewrite_location: !no_rewrite_location,
+                index_path: serve_index.clone(),
+                app_js_path: serve_app_js.clone(),
+                worker_js_path: serve_worker_js.clone(),
+                max_response_size: *max_response_size,
+                max_proxy_response_size: *max_proxy_response_size,
+                inject_headers: inject_header.clone(),
+                strip_response_headers: strip_response_headers.clone(),
+                strip_cookies: *strip_cookies,
+                rewrite_cookies: *rewrite_cookies,
+                cookie_jar: cookie_jar.clone(),
+                record_new: record_new.clone(),
+                json: *json,
+                serve_ui: !no_service_worker,
+                concurrent_limit: *concurrent_limit,
+                csp: match (csp_override, no_csp) {
+                    (_, true) => None,
+                    (Some(policy), false) => Some(policy.clone()),
+                    (None, false) => ServerOptions::default().csp,
+                },
+                csp_report_only: *csp_report_only,
+                cors_origin: cors_origin.clone(),
+                cors_credentials: *cors_credentials,
+                reset_cache_control: *reset_cache_control,
+                tls: tls_source,
+                latency_scale: simulate_latency.then(|| latency_scale.unwrap_or(1.0)),
+                round_robin: !no_round_robin,
+                sse_delay: sse_delay_ms.map(Duration::from_millis),
+                watch: *watch,
+                shutdown_timeout: Duration::from_secs(*shutdown_timeout),
+            };
+            if *should_print_routes {
+                print_routes(&har, &options).expect("failed to compute routes from HAR");
+                return;
+            }
+            if *is_dry_run {
+                let issues = dry_run(&har, &options);
+                for issue in &issues {
+                    println!("{}", issue);
+                }
+                if !issues.is_empty() {
+                    println!("found {} issues", issues.len());
+                    std::process::exit(1);
+                }
+                println!("{} is servable", har_path.display());
+                return;
+            }
+            let harbinger_server = build_server(&har, &options)
+                .expect("failed to initialize server from HAR")
+                .ignite()
+                .await
+                .expect("failed to start server");
+            // kept until the server stops
+            let _watcher = if *watch {
+                // build_server manages the table when watching
+                let table = harbinger_server.state::<EntryTable>().unwrap().clone();
+                let (path, page, dedup, verbose) =
+                    (har_path.clone(), page.clone(), *dedup, args.verbose);
+                let reload = move || {
+                    let page_options = ReadOptions {
+                        page_id: page.as_deref(),
+                        ..read_options
+                    };
+                    let har = read_served_har(&path, page_options, dedup, verbose)?;
+                    table.reload(&har, &options)?;
+                    println!("reloaded {}: {} entries", path.display(), har.entries.len());
+                    Ok(())
+                };
+                Some(watch_har(har_path, reload).expect("failed to watch HAR"))
             } else {
-                let _ = harbinger_server.launch().await;
+                None
+            };
+            let log_level = args.rocket_log_level();
+            let blackhole_config = match (blackhole_config, blackhole_port) {
+                (Some(path), port) => {
+                    let mut config = BlackholeConfig::read(path).unwrap();
+                    if let Some(port) = port {
+                        config.port = *port;
+                    }
+                    config.log_level = log_level;
+                    Some(config)
+                }
+                (None, Some(port)) => Some(BlackholeConfig {
+                    port: *port,
+                    log_level,
+                    ..Default::default()
+                }),
+                (None, None) => None,
+            };
+            let mut blackholes = Vec::new();
+            // recorded once bound, for the PAC file
+            let https_port = blackhole_https_port.map(BoundPort::new);
+            if let Some(bound_port) = &https_port {
+                let port = bound_port.get();
+                // the HTTPS blackhole follows the same rules as the HTTP one
+                let blackhole = match &blackhole_config {
+                    Some(config) => build_https_blackhole_from_config(&BlackholeConfig {
+                        port,
+                        ..config.clone()
+                    }),
+                    None => build_https_blackhole_from_config(&BlackholeConfig {
+                        port,
+                        log_level,
+                        ..Default::default()
+                    }),
+                };
+                blackholes.push(blackhole.attach(bound_port.clone()));
+            }
+            if let Some(mut config) = blackhole_config {
+                config.https_port = https_port;
+                blackholes.push(build_blackhole_from_config(&config));
+            }
+            let mut ignited = Vec::new();
+            for blackhole in blackholes {
+                ignited.push(
+                    blackhole
+                        .ignite()
+                        .await
+                        .expect("failed to start blackhole server"),
+                );
+            }
+            if let Some(timeout) = timeout {
+                let shutdowns = std::iter::once(harbinger_server.shutdown())
+                    .chain(ignited.iter().map(|blackhole| blackhole.shutdown()))
+                    .collect();
+                shutdown_after(timeout, shutdowns);
+            }
+            let _ = join!(
+                harbinger_server.launch(),
+                join_all(ignited.into_iter().map(|blackhole| blackhole.launch()))
+            );
+            if timed_out() {
+                std::process::exit(1);
             }
         }
         Command::Dump {
             har_path,
+            page,
             output_path,
             raw,
-            ..
+            include_methods,
+            exclude_methods,
+            entry_offset,
+            entry_limit,
+            jobs,
+            only_javascript,
+            pretty_json,
+            unminify_css,
+            unminify_html,
+            flatten,
+            resume,
+            force,
+            incremental,
+            overwrite,
+            no_progress,
+            entry_sort,
         } => {
-            let har = Har::read(har_path).unwrap();
-            match dump(&har, output_path, *raw) {
-                Ok(_) => println!("Dumped HAR to {}", output_path.display()),
-                Err(e) => println!("Failed to dump HAR: {}", e),
+            let page_options = ReadOptions {
+                page_id: page.as_deref(),
+                ..read_options
+            };
+            let har = Har::read_with(har_path, page_options).unwrap();
+            let options = DumpOptions {
+                raw: *raw,
+                include_methods: include_methods.clone(),
+                exclude_methods: exclude_methods.clone(),
+                entry_offset: *entry_offset,
+                entry_limit: *entry_limit,
+                jobs: *jobs,
+                only_javascript: *only_javascript,
+                flatten: *flatten,
+                resume: *resume,
+                force: *force,
+                no_progress: *no_progress,
+                entry_sort: *entry_sort,
+                deadline,
+                incremental: *incremental,
+                overwrite: *overwrite,
+                pretty_json: *pretty_json,
+                unminify_css: *unminify_css,
+                unminify_html: *unminify_html,
+            };
+            match dump(&har, output_path, &options).await {
+                Ok(summary) => {
+                    for failure in &summary.failures {
+                        println!("Failed to dump {}: {}", failure.url, failure.error);
+                    }
+                    println!("Dumped HAR to {}", output_path.display());
+                    if !summary.failures.is_empty() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    println!("Failed to dump HAR: {}", e);
+                    if timed_out() {
+                        std::process::exit(1);
+                    }
+                }
             }
         }
         Command::Guide => {
-            guide::run().await;
+            harbinger::guide::run().await;
+        }
+        Command::Validate { har_path } => {
+            let har = match Har::read_with(har_path, read_options) {
+                Ok(har) => har,
+                Err(err) => {
+                    eprintln!("failed to read {}: {}", har_path.display(), err);
+                    std::process::exit(1);
+                }
+            };
+            let report = validate(&har).await;
+            report.print();
+            if !report.is_servable() {
+                std::process::exit(1);
+            }
+        }
+        Command::Verify {
+            har_path,
+            dump_path,
+        } => {
+            let har = Har::read_with(har_path, read_options).unwrap();
+            match verify(&har, dump_path) {
+                Ok(report) => {
+                    report.print();
+                    if !report.is_clean() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    println!("Failed to verify dump: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Diff {
+            base,
+            updated,
+            context,
+            format,
+        } => {
+            let base = Har::read_with(base, read_options).unwrap();
+            let updated = Har::read_with(updated, read_options).unwrap();
+            if let Err(e) = HarDiff::new(&base, &updated, *context).print(*format) {
+                println!("Failed to diff HARs: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Export {
+            har_path,
+            dump_path,
+            output,
+        } => {
+            let mut har = Har::read_with(har_path, read_options).unwrap();
+            let changed = match apply_overrides(&mut har, std::slice::from_ref(dump_path)) {
+                Ok(changed) => changed,
+                Err(e) => {
+                    println!("Failed to read dumped files: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            match har.write(output) {
+                Ok(()) => println!(
+                    "Embedded {} modified bodies into {}",
+                    changed.len(),
+                    output.display()
+                ),
+                Err(e) => {
+                    println!("Failed to write exported HAR: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Merge {
+            inputs,
+            output,
+            strategy,
+        } => {
+            let hars = inputs
+                .iter()
+                .map(|path| Har::read_with(path, read_options).unwrap())
+                .collect();
+            let merged = Har::merge_all_with(hars, *strategy);
+            match merged.write(output) {
+                Ok(()) => println!(
+                    "Merged {} entries into {}",
+                    merged.entries.len(),
+                    output.display()
+                ),
+                Err(e) => {
+                    println!("Failed to write merged HAR: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Stats {
+            har_path,
+            format,
+            party_scope,
+            entry_sort,
+        } => {
+            let har = Har::read_with(har_path, read_options).unwrap();
+            let summary = HarSummary::new(&har);
+            match format {
+                StatsFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+                }
+                StatsFormat::Text => {
+                    summary.print();
+                    if let Err(e) = print_party_summary(&har, *party_scope) {
+                        println!("Failed to classify entries: {}", e);
+                    }
+                    print_waterfall(&har, *entry_sort);
+                }
+            }
+        }
+        Command::Search {
+            har_path,
+            pattern,
+            regex,
+            method,
+            status,
+            format,
+            body,
+        } => {
+            let url = UrlPattern::parse(pattern, *regex).unwrap_or_else(|e| {
+                eprintln!("invalid pattern {}: {}", pattern, e);
+                std::process::exit(2);
+            });
+            let query = SearchQuery {
+                url,
+                method: method.clone(),
+                status: *status,
+            };
+            let har = Har::read_with(har_path, read_options).unwrap();
+            let results = search(&har, &query, *body);
+            if let Err(e) = print_results(&results, *format) {
+                println!("Failed to print entries: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Completions { shell } => {
+            let mut command = Args::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(*shell, &mut command, name, &mut std::io::stdout());
         }
     }
 }
@@ -0,0 +1,160 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use har::{
+    v1_2::{Cache, Content, Creator, Entries, Headers, Log, Request, Response, Timings},
+    Har as HarExt, Spec,
+};
+use log::info;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::har::read_har;
+
+// a request/response pair forwarded to the proxy
+pub struct Exchange {
+    pub started_date_time: String,
+    pub time: Duration,
+    pub method: String,
+    // the request's path and query
+    pub uri: String,
+    pub req_headers: Vec<(String, String)>,
+    pub status: u16,
+    pub status_text: String,
+    pub res_headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+// collects proxied requests as new HAR entries, to be appended to a HAR file
+// when the server shuts down
+pub struct Recorder {
+    path: PathBuf,
+    // the HAR origin recorded requests are attributed to, e.g.
+    // `https://example.com`
+    origin: String,
+    pageref: Option<String>,
+    entries: Mutex<Vec<Entries>>,
+}
+
+fn to_headers(headers: Vec<(String, String)>) -> Vec<Headers> {
+    headers
+        .into_iter()
+        .map(|(name, value)| Headers {
+            name,
+            value,
+            comment: None,
+        })
+        .collect()
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, origin: String, pageref: Option<String>) -> Self {
+        Recorder {
+            path,
+            origin,
+            pageref,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, exchange: Exchange) {
+        let mime_type = exchange
+            .res_headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.clone());
+        // bodies are stored base64 encoded, which Entry::res_body decodes
+        let content = Content {
+            size: exchange.body.len() as i64,
+            compression: None,
+            mime_type,
+            text: Some(STANDARD.encode(&exchange.body)),
+            encoding: Some("base64".to_string()),
+            comment: None,
+        };
+        let time = exchange.time.as_secs_f64() * 1000.0;
+        let entry = Entries {
+            pageref: self.pageref.clone(),
+            started_date_time: exchange.started_date_time,
+            time,
+            request: Request {
+                method: exchange.method,
+                url: format!("{}{}", self.origin, exchange.uri),
+                http_version: "HTTP/1.1".to_string(),
+                cookies: Vec::new(),
+                headers: to_headers(exchange.req_headers),
+                query_string: Vec::new(),
+                post_data: None,
+                headers_size: -1,
+                body_size: 0,
+                comment: None,
+            },
+            response: Response {
+                status: exchange.status as i64,
+                status_text: exchange.status_text,
+                http_version: "HTTP/1.1".to_string(),
+                cookies: Vec::new(),
+                headers: to_headers(exchange.res_headers),
+                content,
+                redirect_url: None,
+                headers_size: -1,
+                body_size: exchange.body.len() as i64,
+                comment: None,
+            },
+            cache: Cache {
+                before_request: None,
+                after_request: None,
+                comment: None,
+            },
+            timings: Timings {
+                blocked: None,
+                dns: None,
+                connect: None,
+                send: 0.0,
+                wait: time,
+                receive: 0.0,
+                ssl: None,
+                comment: None,
+            },
+            server_ip_address: None,
+            connection: None,
+            comment: None,
+        };
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    // appends the recorded entries to the HAR at `path`, creating it if it
+    // doesn't exist yet
+    pub fn write(&self) -> Result<()> {
+        let entries = std::mem::take(&mut *self.entries.lock().unwrap());
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut log = if self.path.try_exists()? {
+            read_har(&self.path)?
+        } else {
+            Log {
+                creator: Creator {
+                    name: "harbinger".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    comment: None,
+                },
+                browser: None,
+                pages: None,
+                entries: Vec::new(),
+                comment: None,
+            }
+        };
+        info!(
+            "recording {} new entries to {}",
+            entries.len(),
+            self.path.display()
+        );
+        log.entries.extend(entries);
+        let har = HarExt {
+            log: Spec::V1_2(log),
+        };
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&har)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use glob::Pattern;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::har::{Entry, Har};
+
+// how `search` prints the entries it finds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SearchFormat {
+    // a line per entry, followed by its body preview if asked for
+    Text,
+    // a JSON array of entries
+    Json,
+}
+
+// what entry URLs are matched against
+#[derive(Clone, Debug)]
+pub enum UrlPattern {
+    // a glob matching the whole URL, where `*` also matches `/`
+    Glob(Pattern),
+    // a regex matching anywhere in the URL
+    Regex(Regex),
+}
+
+impl UrlPattern {
+    pub fn parse(pattern: &str, regex: bool) -> Result<Self> {
+        Ok(if regex {
+            UrlPattern::Regex(Regex::new(pattern)?)
+        } else {
+            UrlPattern::Glob(Pattern::new(pattern)?)
+        })
+    }
+
+    pub fn matches(&self, url: &str) -> bool {
+        match self {
+            UrlPattern::Glob(pattern) => pattern.matches(url),
+            UrlPattern::Regex(regex) => regex.is_match(url),
+        }
+    }
+}
+
+pub struct SearchQuery {
+    pub url: UrlPattern,
+    // compared case-insensitively
+    pub method: Option<String>,
+    pub status: Option<u16>,
+}
+
+impl SearchQuery {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        self.url.matches(entry.url())
+            && self.method.as_ref().map_or(true, |method| {
+                entry
+                    .method()
+                    .is_ok_and(|m| m.as_str().eq_ignore_ascii_case(method))
+            })
+            && self
+                .status
+                .map_or(true, |status| entry.status() == i64::from(status))
+    }
+}
+
+#[derive(Serialize)]
+pub struct SearchResult {
+    entry: usize,
+    method: String,
+    url: String,
+    status: i64,
+    content_type: Option<String>,
+    // of the decoded response body, or None if it wasn't recorded
+    size: Option<usize>,
+    // the start of the response body, decoded lossily as UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+}
+
+// the entries matching `query`, in recording order, with the first
+// `body_preview` bytes of their response bodies if given
+pub fn search(har: &Har, query: &SearchQuery, body_preview: Option<usize>) -> Vec<SearchResult> {
+    har.entries
+        .iter()
+        .filter(|entry| query.matches(entry))
+        .map(|entry| {
+            let body = entry.res_body();
+            SearchResult {
+                entry: entry.index(),
+                method: entry
+                    .method()
+                    .map_or("???".to_string(), |method| method.to_string()),
+                url: entry.url().to_string(),
+                status: entry.status(),
+                content_type: entry.content_type().map(str::to_string),
+                size: body.as_ref().map(Vec::len),
+                body: body_preview.map(|limit| {
+                    let body = body.unwrap_or_default();
+                    String::from_utf8_lossy(&body[..limit.min(body.len())]).into_owned()
+                }),
+            }
+        })
+        .collect()
+}
+
+pub fn print_results(results: &[SearchResult], format: SearchFormat) -> Result<()> {
+    match format {
+        SearchFormat::Json => println!("{}", serde_json::to_string_pretty(results)?),
+        SearchFormat::Text => {
+            for result in results {
+                let size = result
+                    .size
+                    .map_or("-".to_string(), |size| format!("{}B", size));
+                println!(
+                    "#{:<4} {:<7} {} {:<24} {:>9} {}",
+                    result.entry,
+                    result.method,
+                    result.status,
+                    result.content_type.as_deref().unwrap_or("-"),
+                    size,
+                    result.url
+                );
+                if let Some(body) = &result.body {
+                    for line in body.lines() {
+                        println!("      | {}", line);
+                    }
+                }
+            }
+            println!("{} matching entries", results.len());
+        }
+    }
+    Ok(())
+}
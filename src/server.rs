@@ -1,14 +1,88 @@
 use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use glob::Pattern;
 use log::{info, warn};
-use rocket::config::Config as RocketConfig;
-use rocket::http::{uri, ContentType, Status};
+use rand::Rng;
+use regex::Regex;
+use rocket::config::{Config as RocketConfig, LogLevel, TlsConfig};
+use rocket::data::{ByteUnit, ToByteUnit};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::http::{uri, ContentType, RawStr, Status, StatusClass};
+use rocket::request::FromRequest;
+use rocket::response::stream::{Event, EventStream};
+use rocket::response::{self, Responder};
 use rocket::route::{Handler, Outcome};
-use rocket::{get, routes, Response, State};
-use rocket::{http::Method, Build, Data, Request, Rocket, Route};
-use std::io;
+use rocket::{get, post, routes, Response, State};
+use rocket::{http::Method, Build, Data, Orbit, Request, Rocket, Route};
+use rocket_ws::{Message, WebSocket};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use url::form_urlencoded;
 
-use crate::har::{Entry, Har};
+use crate::cookies::CookieJar;
+use crate::error::HarbingerError;
+use crate::har::{ascii_host, parse_sse_events, Entry, Har, SseEvent};
+use crate::recorder::{Exchange, Recorder};
+
+pub const DEFAULT_REQUEST_BODY_LIMIT: u64 = 1024 * 1024;
+pub const DEFAULT_PORT: u16 = 8000;
+pub const DEFAULT_TLS_PORT: u16 = 8443;
+// how long shutting down waits for requests being handled to finish
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+// between the events of a recorded event stream that has no receive time
+pub const DEFAULT_SSE_DELAY: Duration = Duration::from_millis(100);
+
+// where the generated self-signed certificate is written, for users to trust
+// in their browser
+const SELF_SIGNED_CERT_PATH: &str = "harbinger_ca.pem";
+
+// permissive enough for recorded pages to run, while keeping the service
+// worker on the harbinger origin
+const DEFAULT_CSP_COMPONENTS: [&str; 3] = [
+    "base-uri 'self'",
+    "default-src * 'unsafe-inline' 'unsafe-eval'",
+    "worker-src 'self'",
+];
+
+// where browsers send reports of violations of a report-only CSP, and the
+// name it's given in the Reporting-Endpoints header
+const CSP_REPORT_PATH: &str = "/harbinger/csp-report";
+const CSP_REPORT_ENDPOINT: &str = "harbinger-csp";
+// the GET routes build_server mounts for the UI and health check, which
+// entries can't be served at
+pub const INTERNAL_ROUTE_PATHS: &[&str] = &[
+    "/harbinger",
+    "/harbinger_app.js",
+    "/harbinger_worker.js",
+    "/harbinger/health",
+];
+// below every entry and proxy route, so recorded OPTIONS responses and the
+// proxy still take precedence over generic preflight responses
+const PREFLIGHT_RANK: isize = 100;
+const PREFLIGHT_METHODS: &str = "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS";
+// while watching, entries are served by catch-all routes just ahead of the
+// proxy's, which have rocket's default rank of -1
+const WATCH_RANK: isize = -2;
+const ALL_METHODS: &[Method] = &[
+    Method::Get,
+    Method::Put,
+    Method::Post,
+    Method::Delete,
+    Method::Options,
+    Method::Head,
+    Method::Trace,
+    Method::Connect,
+    Method::Patch,
+];
 
 const UNFORWARDED_HEADERS: &[&str] = &[
     // Security headers we want to override
@@ -24,96 +98,1233 @@ const UNFORWARDED_HEADERS: &[&str] = &[
     "content-length",
 ];
 
+// request headers that only apply to the connection to harbinger, so they
+// aren't passed on to the proxy
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "te",
+    "trailers",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// decides which recorded response headers are forwarded to the client,
+// combining UNFORWARDED_HEADERS with any user-supplied names. set-cookie
+// isn't stripped unless asked for, since replaying sessions depends on it
+pub struct HeaderFilter {
+    stripped: HashSet<String>,
+}
+
+impl HeaderFilter {
+    pub fn new(extra_headers: &[String], strip_cookies: bool) -> Self {
+        let stripped = UNFORWARDED_HEADERS
+            .iter()
+            .map(|name| name.to_string())
+            .chain(extra_headers.iter().map(|name| name.to_ascii_lowercase()))
+            .chain(strip_cookies.then(|| "set-cookie".to_string()))
+            .collect();
+        HeaderFilter { stripped }
+    }
+
+    pub fn is_stripped(&self, name: &str) -> bool {
+        self.stripped.contains(&name.to_ascii_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum RequestLogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum AccessLogFormat {
+    // one JSON object per line
+    Jsonl,
+    // Apache's Combined Log Format
+    Clf,
+}
+
+// where the certificate for serving over HTTPS comes from
+pub enum TlsSource {
+    // generated at startup, and written to SELF_SIGNED_CERT_PATH
+    SelfSigned,
+    // PEM files
+    Files { cert: PathBuf, key: PathBuf },
+}
+
+pub struct ServerOptions {
+    pub port: u16,
+    pub dump_path: Option<PathBuf>,
+    // hand-written overrides, checked before the dump path
+    pub override_dir: Option<PathBuf>,
+    pub proxy: Option<reqwest::Url>,
+    // only proxy requests whose path matches one of these, or every
+    // unmatched request if empty
+    pub proxy_patterns: Vec<Regex>,
+    // request headers not sent to the proxy, besides hop-by-hop ones
+    pub proxy_strip_headers: Vec<String>,
+    pub log_requests: Option<RequestLogFormat>,
+    // append a line for every request to this file
+    pub access_log: Option<(PathBuf, AccessLogFormat)>,
+    pub response_delays: Vec<(Pattern, Duration)>,
+    pub response_delay_jitter: Vec<(Pattern, Duration)>,
+    pub request_body_limit: u64,
+    pub log_level: LogLevel,
+    // serve the HAR's origin as if it were this URL
+    pub base_url: Option<reqwest::Url>,
+    // prefix redirect locations with their hostname, for the service worker
+    pub rewrite_location: bool,
+    // replacements for the bundled harbinger UI, read at request time
+    pub index_path: Option<PathBuf>,
+    pub app_js_path: Option<PathBuf>,
+    pub worker_js_path: Option<PathBuf>,
+    // refuse to serve response bodies larger than this many bytes
+    pub max_response_size: Option<u64>,
+    pub max_proxy_response_size: Option<u64>,
+    // response headers to strip in addition to UNFORWARDED_HEADERS
+    pub strip_response_headers: Vec<String>,
+    // strip set-cookie from HAR responses
+    pub strip_cookies: bool,
+    // make cookies from HAR and proxied responses acceptable to browsers
+    // on harbinger's own host and scheme
+    pub rewrite_cookies: bool,
+    // file to load and persist cookies set by served responses
+    pub cookie_jar: Option<PathBuf>,
+    // append proxied requests to this HAR file on shutdown
+    pub record_new: Option<PathBuf>,
+    // print machine-readable output, e.g. when announcing the port
+    pub json: bool,
+    // serve the harbinger UI and service worker
+    pub serve_ui: bool,
+    // respond with 503 rather than handle more than this many entry requests
+    // at once
+    pub concurrent_limit: Option<NonZeroUsize>,
+    // content-security-policy for HAR responses, or None to send no CSP
+    pub csp: Option<String>,
+    // only report CSP violations to CSP_REPORT_PATH rather than blocking them
+    pub csp_report_only: bool,
+    // allow cross-origin requests from this origin, answering preflights
+    pub cors_origin: Option<String>,
+    // also allow them to send credentials
+    pub cors_credentials: bool,
+    // send HAR responses with `cache-control: no-store` rather than their
+    // recorded cache-control
+    pub reset_cache_control: bool,
+    // serve over HTTPS rather than HTTP
+    pub tls: Option<TlsSource>,
+    // delay responses by each entry's recorded wait time, multiplied by
+    // this, or don't if None
+    pub latency_scale: Option<f64>,
+    // rotate through the entries matching a request, rather than always
+    // serving the first
+    pub round_robin: bool,
+    // between the events of recorded event streams, rather than spreading
+    // them over the entry's receive time
+    pub sse_delay: Option<Duration>,
+    // headers added to every HAR and proxied response, replacing any with
+    // the same name
+    pub inject_headers: Vec<(String, String)>,
+    // serve entries through an EntryTable that can be reloaded
+    pub watch: bool,
+    // how long a shutdown waits for requests being handled to finish
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            port: DEFAULT_PORT,
+            dump_path: None,
+            override_dir: None,
+            proxy: None,
+            proxy_patterns: Vec::new(),
+            proxy_strip_headers: Vec::new(),
+            log_requests: None,
+            access_log: None,
+            response_delays: Vec::new(),
+            response_delay_jitter: Vec::new(),
+            request_body_limit: DEFAULT_REQUEST_BODY_LIMIT,
+            log_level: LogLevel::Debug,
+            base_url: None,
+            rewrite_location: true,
+            index_path: None,
+            app_js_path: None,
+            worker_js_path: None,
+            max_response_size: None,
+            max_proxy_response_size: None,
+            strip_response_headers: Vec::new(),
+            strip_cookies: false,
+            rewrite_cookies: false,
+            cookie_jar: None,
+            record_new: None,
+            json: false,
+            serve_ui: true,
+            concurrent_limit: None,
+            csp: Some(DEFAULT_CSP_COMPONENTS.join("; ")),
+            csp_report_only: false,
+            cors_origin: None,
+            cors_credentials: false,
+            reset_cache_control: false,
+            tls: None,
+            latency_scale: None,
+            round_robin: true,
+            sse_delay: None,
+            inject_headers: Vec::new(),
+            watch: false,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+}
+
+impl ServerOptions {
+    // the directories response bodies are read from instead of the HAR, in
+    // the order they're checked
+    fn override_dirs(&self) -> impl Iterator<Item = &PathBuf> {
+        self.override_dir.iter().chain(&self.dump_path)
+    }
+}
+
+pub fn parse_latency_scale(scale: &str) -> Result<f64, String> {
+    let scale = scale
+        .parse::<f64>()
+        .map_err(|err| format!("invalid scale {}: {}", scale, err))?;
+    if !scale.is_finite() || scale < 0.0 {
+        return Err(format!(
+            "scale must be a non-negative number, got {}",
+            scale
+        ));
+    }
+    Ok(scale)
+}
+
+// parses a `<name>:<value>` header, e.g. `X-Harbinger: 1`
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("expected <name>:<value>, got {}", header))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("missing header name in {}", header));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+// parses a `<glob>=<ms>` rule, e.g. `*/api/*=500`
+pub fn parse_delay_rule(rule: &str) -> Result<(Pattern, Duration), String> {
+    let (glob, ms) = rule
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected <url_pattern>=<ms>, got {}", rule))?;
+    let pattern = Pattern::new(glob).map_err(|err| format!("invalid pattern {}: {}", glob, err))?;
+    let ms = ms
+        .parse::<u64>()
+        .map_err(|err| format!("invalid delay {}: {}", ms, err))?;
+    Ok((pattern, Duration::from_millis(ms)))
+}
+
 struct Config {
-    port: u16,
+    // whether the UI and service worker are served, and so whether responses
+    // should be adapted for the service worker
+    serve_ui: bool,
+    // the host the service worker treats as the origin
     origin_host: String,
+    // the origin host the HAR was recorded from
+    har_origin_host: String,
+    base_url: Option<reqwest::Url>,
+    rewrite_location: bool,
+    header_filter: HeaderFilter,
+    index_path: Option<PathBuf>,
+    app_js_path: Option<PathBuf>,
+    worker_js_path: Option<PathBuf>,
+    entry_count: usize,
+    start_time: Instant,
+    response_delays: Vec<(Pattern, Duration)>,
+    response_delay_jitter: Vec<(Pattern, Duration)>,
+    request_body_limit: u64,
+    cookie_jar: Option<CookieJar>,
+    recorder: Option<Recorder>,
+    // permits for requests being handled by an EntryHandler
+    concurrency_limit: Option<Semaphore>,
+    csp: Option<String>,
+    csp_report_only: bool,
+    reset_cache_control: bool,
+    rewrite_cookies: bool,
+    latency_scale: Option<f64>,
+    sse_delay: Option<Duration>,
+    inject_headers: Vec<(String, String)>,
+}
+
+impl Config {
+    // adds the --inject-header headers, replacing any the response already
+    // has by those names. a name given more than once is sent once per value
+    fn inject_headers(&self, res: &mut Response<'_>) {
+        for (name, _) in &self.inject_headers {
+            res.remove_header(name);
+        }
+        for (name, value) in &self.inject_headers {
+            res.adjoin_raw_header(name.clone(), value.clone());
+        }
+    }
+
+    fn rewrite_body(&self, body: Vec<u8>) -> Vec<u8> {
+        let Some(base_url) = &self.base_url else {
+            return body;
+        };
+        match String::from_utf8(body) {
+            Ok(text) => rewrite_origin(&text, &self.har_origin_host, base_url).into_bytes(),
+            Err(err) => err.into_bytes(),
+        }
+    }
+
+    // sums every matching delay rule, plus a uniformly random amount of
+    // jitter for every matching jitter rule
+    fn response_delay(&self, url: &str) -> Duration {
+        let mut delay: Duration = self
+            .response_delays
+            .iter()
+            .filter(|(pattern, _)| pattern.matches(url))
+            .map(|(_, delay)| *delay)
+            .sum();
+        let mut rng = rand::thread_rng();
+        for (pattern, max_jitter) in &self.response_delay_jitter {
+            if pattern.matches(url) {
+                delay += rng.gen_range(Duration::ZERO..=*max_jitter);
+            }
+        }
+        delay
+    }
+
+    // the delay between an event stream's events: --sse-delay-ms, or the
+    // entry's receive time divided evenly between them
+    fn sse_delay(&self, entry: &Entry, event_count: usize) -> Duration {
+        if let Some(delay) = self.sse_delay {
+            return delay;
+        }
+        match entry.receive_ms() {
+            Some(receive) if receive > 0 && event_count > 1 => {
+                Duration::from_millis(receive / (event_count as u64 - 1))
+            }
+            _ => DEFAULT_SSE_DELAY,
+        }
+    }
+
+    // the entry's recorded wait time, scaled by --latency-scale, when
+    // simulating latency
+    fn simulated_latency(&self, entry: &Entry) -> Duration {
+        match (self.latency_scale, entry.wait_ms()) {
+            (Some(scale), Some(wait)) => Duration::from_secs_f64(wait as f64 * scale / 1000.0),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+// the bundled harbinger UI, or a replacement for it read from disk
+struct UiContent {
+    content_type: ContentType,
+    content: String,
+    from_file: bool,
+}
+
+impl UiContent {
+    fn load(
+        content_type: ContentType,
+        bundled: &'static str,
+        path: Option<&PathBuf>,
+    ) -> std::result::Result<UiContent, Status> {
+        let Some(path) = path else {
+            return Ok(UiContent {
+                content_type,
+                content: bundled.to_string(),
+                from_file: false,
+            });
+        };
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(UiContent {
+                content_type,
+                content,
+                from_file: true,
+            }),
+            Err(err) => {
+                warn!("failed to read {}: {:?}", path.display(), err);
+                Err(Status::InternalServerError)
+            }
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for UiContent {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut res = (self.content_type, self.content).respond_to(req)?;
+        if self.from_file {
+            res.set_raw_header("cache-control", "no-cache");
+        }
+        Ok(res)
+    }
 }
 
 #[get("/harbinger")]
-fn serve_index() -> (ContentType, &'static str) {
-    let content = include_str!("../static/index.html");
-    (ContentType::HTML, content)
+fn serve_index(config: &State<Config>) -> std::result::Result<UiContent, Status> {
+    UiContent::load(
+        ContentType::HTML,
+        include_str!("../static/index.html"),
+        config.index_path.as_ref(),
+    )
 }
 
 #[get("/harbinger_app.js")]
-fn serve_app_js() -> (ContentType, &'static str) {
-    let content = include_str!("../static/harbinger_app.js");
-    (ContentType::JavaScript, content)
+fn serve_app_js(config: &State<Config>) -> std::result::Result<UiContent, Status> {
+    UiContent::load(
+        ContentType::JavaScript,
+        include_str!("../static/harbinger_app.js"),
+        config.app_js_path.as_ref(),
+    )
 }
 
 #[get("/harbinger_worker.js")]
-fn serve_worker_js(config: &State<Config>) -> (ContentType, String) {
-    let content = include_str!("../static/harbinger_worker.js")
-        .replace("HARBINGER_TMPL_PORT", &config.port.to_string())
-        .replace("HARBINGER_TMPL_ORIGIN_HOST", &config.origin_host);
-    (ContentType::JavaScript, content)
+fn serve_worker_js(
+    config: &State<Config>,
+    rocket_config: &RocketConfig,
+) -> std::result::Result<UiContent, Status> {
+    let mut worker = UiContent::load(
+        ContentType::JavaScript,
+        include_str!("../static/harbinger_worker.js"),
+        config.worker_js_path.as_ref(),
+    )?;
+    worker.content = worker
+        .content
+        // the bound port, which differs from the configured one for --port 0
+        .replace("HARBINGER_TMPL_PORT", &rocket_config.port.to_string())
+        .replace("HARBINGER_TMPL_ORIGIN_HOST", &config.origin_host)
+        .replace(
+            "HARBINGER_TMPL_PROTOCOL",
+            if rocket_config.tls_enabled() {
+                "https"
+            } else {
+                "http"
+            },
+        );
+    Ok(worker)
+}
+
+#[get("/harbinger/health")]
+fn serve_health(
+    config: &State<Config>,
+    entry_table: Option<&State<EntryTable>>,
+) -> (ContentType, String) {
+    // a watched HAR's entries change as it's reloaded
+    let entries = entry_table.map_or(config.entry_count, |table| table.entry_count());
+    let uptime = config.start_time.elapsed().as_secs();
+    // uptime_secs is the documented name, and uptime_seconds kept for
+    // probes already reading it
+    let health = serde_json::json!({
+        "status": "ok",
+        "entries": entries,
+        "origin_host": config.har_origin_host,
+        "uptime_secs": uptime,
+        "uptime_seconds": uptime,
+        "version": env!("CARGO_PKG_VERSION"),
+    });
+    (ContentType::JSON, health.to_string())
+}
+
+// logs the violation in a report sent via report-uri (a `csp-report` object)
+// or report-to (a `body` object, with camelCased fields)
+fn log_csp_violation(report: &serde_json::Value) {
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| report.get(name)?.as_str())
+            .unwrap_or("<unknown>")
+            .to_string()
+    };
+    tracing::warn!(
+        document = %field(&["document-uri", "documentURL"]),
+        directive = %field(&[
+            "effective-directive",
+            "effectiveDirective",
+            "violated-directive"
+        ]),
+        blocked = %field(&["blocked-uri", "blockedURL"]),
+        "CSP violation"
+    );
+}
+
+#[post("/harbinger/csp-report", data = "<body>")]
+fn receive_csp_report(body: String) -> Status {
+    let Ok(report) = serde_json::from_str::<serde_json::Value>(&body) else {
+        warn!("received malformed CSP report: {}", body);
+        return Status::BadRequest;
+    };
+    match &report {
+        serde_json::Value::Array(reports) => reports
+            .iter()
+            .filter_map(|report| report.get("body"))
+            .for_each(log_csp_violation),
+        report => report
+            .get("csp-report")
+            .into_iter()
+            .for_each(log_csp_violation),
+    }
+    Status::NoContent
+}
+
+// the file overriding an entry's response body: its dump path in the first
+// of `dirs` that has one
+pub fn find_override(entry: &Entry, dirs: &[PathBuf]) -> Result<Option<PathBuf>> {
+    for dir in dirs {
+        let path = entry.get_dump_path(dir)?;
+        if path.exists() {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
 }
 
-fn get_entry_route_path(entry_uri: &uri::Reference, origin_host: &str) -> Result<String> {
-    let hostname = entry_uri.authority().unwrap().host();
-    if hostname == origin_host {
-        Ok(format!("/{}", entry_uri.path()))
+// the route path segment for an entry's raw path segment. rocket compares
+// a route's static segments as written against the request's segments
+// once they're percent-decoded, so e.g. `%40admin` has to be registered as
+// `@admin` to be matched, however the request encodes it. segments that
+// decode to characters a route can't contain, like `/` from `%2F`, become
+// wildcards, and EntryHandler compares the decoded paths instead
+fn route_segment(segment: &RawStr) -> String {
+    let is_route_char = |c: char| c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=:@".contains(c);
+    match segment.percent_decode() {
+        Ok(decoded) if decoded.chars().all(is_route_char) => decoded.into_owned(),
+        _ => "<_>".to_string(),
+    }
+}
+
+// entries on the origin host are served at their path, and entries on any
+// other host (including subdomains of the origin) at `/<hostname><path>`,
+// for the service worker to route them to. without an origin host, every
+// entry is served at its own path. neither the query string nor the
+// fragment is part of the route, since EntryHandler matches queries itself
+pub fn get_entry_route_path(
+    entry_uri: &uri::Reference,
+    origin_host: Option<&str>,
+) -> Result<String> {
+    let hostname = entry_uri
+        .authority()
+        .ok_or_else(|| HarbingerError::InvalidHarEntryUri {
+            uri: entry_uri.to_string(),
+        })?
+        .host();
+    let segments: Vec<String> = entry_uri.path().raw_segments().map(route_segment).collect();
+    let path = format!("/{}", segments.join("/"));
+    match origin_host {
+        Some(origin_host) if ascii_host(hostname) != ascii_host(origin_host) => {
+            Ok(format!("/{}{}", hostname, path))
+        }
+        _ => Ok(path),
+    }
+}
+
+// the query parameter that picks out the entries recorded with a particular
+// request body, e.g. `/graphql?_harbinger_body_hash=<sha256>`
+const BODY_HASH_PARAM: &str = "_harbinger_body_hash";
+
+// a route serving the group of HAR entries that share a method and path
+pub struct HarRoute {
+    pub method: Method,
+    pub path: String,
+    pub entries: Vec<Entry>,
+    // set for routes serving only the entries with this request body hash
+    pub body_hash: Option<String>,
+    // rocket ignores queries when checking for collisions, so routes that
+    // differ only by body hash are given distinct ranks
+    pub rank: Option<isize>,
+}
+
+// computes the entry routes build_server registers, ordered by the position
+// of their first entry in the HAR. entries from different hosts that end up
+// at the same path share a route, which matches requests to entries by
+// body. entries with a request body can also be requested directly through
+// a route per body hash
+pub fn har_routes(har: &Har, origin_host: Option<&str>) -> Result<Vec<HarRoute>> {
+    let mut routes: Vec<HarRoute> = Vec::new();
+    let mut body_hash_routes: Vec<HarRoute> = Vec::new();
+    for ((method, _, body_hash), entries) in har.entries()? {
+        let path = get_entry_route_path(&entries[0].uri()?, origin_host)?;
+        let entries: Vec<Entry> = entries.into_iter().cloned().collect();
+        if let Some(body_hash) = body_hash {
+            let siblings = body_hash_routes
+                .iter()
+                .filter(|route| {
+                    route.method == method && route.path.starts_with(&format!("{}?", path))
+                })
+                .count();
+            body_hash_routes.push(HarRoute {
+                method,
+                path: format!("{}?{}={}", path, BODY_HASH_PARAM, body_hash),
+                entries: entries.clone(),
+                body_hash: Some(body_hash),
+                // ahead of rocket's default ranks, which go down to -12
+                rank: Some(-13 - siblings as isize),
+            });
+        }
+        match routes
+            .iter_mut()
+            .find(|route| route.method == method && route.path == path)
+        {
+            Some(route) => route.entries.extend(entries),
+            None => routes.push(HarRoute {
+                method,
+                path,
+                entries,
+                body_hash: None,
+                rank: None,
+            }),
+        }
+    }
+    routes.extend(body_hash_routes);
+    for route in &mut routes {
+        route.entries.sort_by_key(|entry| entry.index());
+    }
+    routes.sort_by_key(|route| route.entries[0].index());
+    Ok(routes)
+}
+
+// prints the entry routes a server built with `options` would register
+pub fn print_routes(har: &Har, options: &ServerOptions) -> Result<()> {
+    let origin_host = har.origin_host()?;
+    let routes = har_routes(har, options.serve_ui.then_some(origin_host.as_str()))?;
+    if options.json {
+        let routes: Vec<serde_json::Value> = routes
+            .iter()
+            .flat_map(|route| {
+                route.entries.iter().map(move |entry| {
+                    serde_json::json!({
+                        "method": route.method.as_str(),
+                        "path": route.path,
+                        "entry": entry.index(),
+                        "url": entry.url(),
+                    })
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&routes)?);
     } else {
-        Ok(format!("/{}{}", hostname, entry_uri.path()))
+        for route in &routes {
+            for entry in &route.entries {
+                println!(
+                    "{} {} [from HAR entry #{}: {}]",
+                    route.method,
+                    route.path,
+                    entry.index(),
+                    entry.url()
+                );
+            }
+        }
     }
+    Ok(())
 }
 
-pub fn build_server(
-    har: &Har,
-    port: u16,
-    dump_path: Option<&PathBuf>,
-    proxy: Option<&reqwest::Url>,
-) -> Result<Rocket<Build>> {
-    if let Some(path) = dump_path {
+// checks that build_server would succeed and that every entry is reachable,
+// returning a description of each problem found
+pub fn dry_run(har: &Har, options: &ServerOptions) -> Vec<String> {
+    let mut issues = Vec::new();
+    for path in options.override_dirs() {
+        if !path.exists() {
+            issues.push(format!("{} doesn't exist", path.display()));
+            return issues;
+        }
+    }
+    let origin_host = match har.origin_host() {
+        Ok(host) => host,
+        Err(err) => {
+            issues.push(format!("couldn't determine origin host: {}", err));
+            return issues;
+        }
+    };
+
+    let mut seen = HashMap::new();
+    for entry in har.entries.iter().filter(|entry| !entry.is_data_uri()) {
+        let (method, uri) = match (entry.method(), entry.uri()) {
+            (Ok(method), Ok(uri)) => (method, uri),
+            (Err(err), _) | (_, Err(err)) => {
+                issues.push(format!("entry #{}: {}", entry.index(), err));
+                continue;
+            }
+        };
+        let route_origin_host = options.serve_ui.then_some(origin_host.as_str());
+        let route_path = match get_entry_route_path(&uri, route_origin_host) {
+            Ok(path) => path,
+            Err(err) => {
+                issues.push(format!("entry #{}: {}", entry.index(), err));
+                continue;
+            }
+        };
+        if let Err(err) = uri::Origin::parse_route(&route_path) {
+            issues.push(format!(
+                "entry #{}: invalid route path {}: {}",
+                entry.index(),
+                route_path,
+                err
+            ));
+        }
+        for dir in options.override_dirs() {
+            match entry.get_dump_path(dir) {
+                Ok(path) if path.exists() => {
+                    if let Err(err) = std::fs::File::open(&path) {
+                        issues.push(format!(
+                            "entry #{}: override file {} isn't readable: {}",
+                            entry.index(),
+                            path.display(),
+                            err
+                        ));
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => issues.push(format!("entry #{}: {}", entry.index(), err)),
+            }
+        }
+        let query = normalize_query(uri.query().map(|q| q.as_str())).map(str::to_string);
+        // routes with wildcard segments are shared by entries at different
+        // paths, so the decoded path tells them apart
+        let segments: Vec<String> = uri.path().segments().map(str::to_string).collect();
+        let key = (
+            method,
+            route_path,
+            segments,
+            query,
+            entry.req_body().map(<[u8]>::to_vec),
+        );
+        if let Some(first_index) = seen.insert(key, entry.index()) {
+            issues.push(format!(
+                "entry #{}: {} {} collides with entry #{}, and will never be served",
+                entry.index(),
+                method,
+                uri,
+                first_index
+            ));
+        }
+    }
+
+    if issues.is_empty() {
+        if let Err(err) = build_server(har, options) {
+            issues.push(format!("failed to build server: {}", err));
+        }
+    }
+    issues
+}
+
+// a certificate for localhost and the origin host, so the HAR can be served
+// under either name
+fn generate_self_signed_cert(origin_host: &str) -> Result<(String, String)> {
+    let names = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        origin_host.to_string(),
+    ];
+    let cert = rcgen::generate_simple_self_signed(names)?;
+    Ok((cert.serialize_pem()?, cert.serialize_private_key_pem()))
+}
+
+fn tls_config(tls: &TlsSource, origin_host: &str) -> Result<TlsConfig> {
+    match tls {
+        TlsSource::SelfSigned => {
+            let (cert, key) = generate_self_signed_cert(origin_host)?;
+            std::fs::write(SELF_SIGNED_CERT_PATH, &cert)?;
+            info!(
+                "wrote self-signed certificate to {}, trust it in your browser to avoid warnings",
+                SELF_SIGNED_CERT_PATH
+            );
+            Ok(TlsConfig::from_bytes(cert.as_bytes(), key.as_bytes()))
+        }
+        TlsSource::Files { cert, key } => Ok(TlsConfig::from_paths(cert, key)),
+    }
+}
+
+pub fn build_server(har: &Har, options: &ServerOptions) -> Result<Rocket<Build>> {
+    let port = options.port;
+    for path in options.override_dirs() {
         if !path.try_exists().unwrap() {
-            panic!("dump path {} doesn't exist", path.display());
+            panic!("{} doesn't exist", path.display());
         }
     }
 
-    let origin_host = har.origin_host()?;
+    let har_origin_host = har.origin_host()?;
+    let origin_host = match options.base_url.as_ref().and_then(|url| url.host_str()) {
+        Some(host) => host.to_string(),
+        None => har_origin_host.clone(),
+    };
 
     let mut entry_routes = Vec::new();
-    let mut routed_paths = Vec::new();
-    for ((method, path), entries) in har.entries()?.iter() {
-        let handler = EntryHandler {
-            entries: entries.iter().cloned().cloned().collect(),
-            dump_path: dump_path.cloned(),
+    let entry_table = if options.watch {
+        let table = EntryTable {
+            routes: Arc::new(RwLock::new(Arc::new(har_entry_routes(har, options)?))),
+            entry_count: Arc::new(AtomicUsize::new(har.entries.len())),
         };
-        let route_path = get_entry_route_path(&entries[0].uri()?, &origin_host)?;
-        entry_routes.push(Route::new(*method, &route_path, handler));
-        routed_paths.push(path);
-    }
+        for method in ALL_METHODS {
+            let handler = WatchedRoutes {
+                table: table.clone(),
+            };
+            entry_routes.push(Route::ranked(WATCH_RANK, *method, "/<any..>", handler));
+        }
+        Some(table)
+    } else {
+        for route in har_entry_routes(har, options)? {
+            entry_routes.push(match route.rank {
+                Some(rank) => Route::ranked(rank, route.method, &route.path, route.handler),
+                None => Route::new(route.method, &route.path, route.handler),
+            });
+        }
+        None
+    };
 
-    if let Some(proxy_url) = proxy {
-        use rocket::http::Method::*;
-        for method in &[Get, Put, Post, Delete, Options, Head, Trace, Connect, Patch] {
+    if let Some(proxy_url) = &options.proxy {
+        // the patterns were each compiled when parsing the arguments, so
+        // their alternation always compiles too
+        let path_pattern = (!options.proxy_patterns.is_empty()).then(|| {
+            let alternatives: Vec<String> = options
+                .proxy_patterns
+                .iter()
+                .map(|pattern| format!("(?:{})", pattern.as_str()))
+                .collect();
+            Regex::new(&alternatives.join("|")).unwrap()
+        });
+        let strip_headers: HashSet<String> = options
+            .proxy_strip_headers
+            .iter()
+            .map(|name| name.to_ascii_lowercase())
+            .collect();
+        for method in ALL_METHODS {
             let handler = ProxyHandler {
                 proxy_url: proxy_url.clone(),
+                path_pattern: path_pattern.clone(),
+                strip_headers: strip_headers.clone(),
+                max_response_size: options.max_proxy_response_size,
             };
             entry_routes.push(Route::new(*method, "/<any..>", handler));
         }
     }
 
-    let server_config = RocketConfig::figment()
+    let mut server_config = RocketConfig::figment()
         .merge(("port", port))
-        .merge(("log_level", "debug"));
+        .merge(("log_level", options.log_level))
+        .merge(("shutdown.grace", options.shutdown_timeout.as_secs()));
+    if let Some(tls) = &options.tls {
+        server_config = server_config.merge(("tls", tls_config(tls, &origin_host)?));
+    }
 
-    let shared_config = Config { port, origin_host };
+    let shared_config = Config {
+        serve_ui: options.serve_ui,
+        origin_host,
+        har_origin_host,
+        base_url: options.base_url.clone(),
+        rewrite_location: options.rewrite_location,
+        header_filter: HeaderFilter::new(&options.strip_response_headers, options.strip_cookies),
+        index_path: options.index_path.clone(),
+        app_js_path: options.app_js_path.clone(),
+        worker_js_path: options.worker_js_path.clone(),
+        entry_count: har.entries.len(),
+        start_time: Instant::now(),
+        response_delays: options.response_delays.clone(),
+        response_delay_jitter: options.response_delay_jitter.clone(),
+        request_body_limit: options.request_body_limit,
+        cookie_jar: options
+            .cookie_jar
+            .as_deref()
+            .map(CookieJar::load)
+            .transpose()?,
+        recorder: match &options.record_new {
+            Some(path) => {
+                let origin = reqwest::Url::parse(har.primary_url())?
+                    .origin()
+                    .ascii_serialization();
+                let pageref = har.pages().first().map(|page| page.id.clone());
+                Some(Recorder::new(path.clone(), origin, pageref))
+            }
+            None => None,
+        },
+        concurrency_limit: options
+            .concurrent_limit
+            .map(|limit| Semaphore::new(limit.get())),
+        csp: options.csp.as_ref().map(|csp| {
+            if options.csp_report_only {
+                // report-uri for browsers that don't support report-to yet
+                format!(
+                    "{}; report-uri {}; report-to {}",
+                    csp, CSP_REPORT_PATH, CSP_REPORT_ENDPOINT
+                )
+            } else {
+                csp.clone()
+            }
+        }),
+        csp_report_only: options.csp_report_only,
+        reset_cache_control: options.reset_cache_control,
+        rewrite_cookies: options.rewrite_cookies,
+        latency_scale: options.latency_scale,
+        sse_delay: options.sse_delay,
+        inject_headers: options.inject_headers.clone(),
+    };
 
-    Ok(rocket::custom(server_config)
-        .mount("/", routes![serve_index, serve_app_js, serve_worker_js])
+    let mut server = rocket::custom(server_config)
+        .mount("/", routes![serve_health])
         .mount("/", entry_routes)
-        .manage(shared_config))
+        .manage(shared_config);
+    if let Some(table) = entry_table {
+        server = server.manage(table);
+    }
+    if options.serve_ui {
+        server = server.mount("/", routes![serve_index, serve_app_js, serve_worker_js]);
+    }
+    if options.csp_report_only {
+        server = server.mount("/", routes![receive_csp_report]);
+    }
+    if let Some(origin) = &options.cors_origin {
+        server = server
+            .mount(
+                "/",
+                vec![Route::ranked(
+                    PREFLIGHT_RANK,
+                    Method::Options,
+                    "/<any..>",
+                    PreflightHandler,
+                )],
+            )
+            .attach(CorsHeaders {
+                origin: origin.clone(),
+                credentials: options.cors_credentials,
+            });
+    }
+
+    if let Some(format) = options.log_requests {
+        server = server.attach(RequestLogger { format });
+    }
+    if let Some((path, format)) = &options.access_log {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        server = server.attach(AccessLog {
+            file: Mutex::new(file),
+            format: *format,
+        });
+    }
+    if port == 0 {
+        server = server.attach(PortAnnouncer { json: options.json });
+    }
+    if options.record_new.is_some() {
+        server = server.attach(RecordingWriter);
+    }
+    if options.cookie_jar.is_some() {
+        server = server.attach(CookieJarWriter);
+    }
+    server = server.attach(RequestDrain::default());
+
+    Ok(server)
+}
+
+// a route for some of the HAR's entries, and the handler serving them
+#[derive(Clone)]
+struct EntryRoute {
+    method: Method,
+    path: String,
+    rank: Option<isize>,
+    handler: EntryHandler,
+}
+
+impl EntryRoute {
+    // whether the route matches a request with these decoded path segments
+    // and query, as rocket would match it if it were mounted
+    fn matches(&self, segments: &[&str], query: Option<uri::Query<'_>>) -> bool {
+        let (path, route_query) = match self.path.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (self.path.as_str(), None),
+        };
+        let route_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let path_matches = route_segments.len() == segments.len()
+            && route_segments
+                .iter()
+                .zip(segments)
+                .all(|(route_segment, segment)| {
+                    *route_segment == "<_>" || route_segment == segment
+                });
+        path_matches
+            && route_query.map_or(true, |route_query| {
+                query.is_some_and(|query| {
+                    query
+                        .raw_segments()
+                        .any(|field| field.as_str() == route_query)
+                })
+            })
+    }
+}
+
+fn har_entry_routes(har: &Har, options: &ServerOptions) -> Result<Vec<EntryRoute>> {
+    let har_origin_host = har.origin_host()?;
+    let route_origin_host = options.serve_ui.then_some(har_origin_host.as_str());
+    let mut routes = Vec::new();
+    for route in har_routes(har, route_origin_host)? {
+        let etags = route
+            .entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.index(),
+                    body_etag(&entry.res_body().unwrap_or_default()),
+                )
+            })
+            .collect();
+        let handler = EntryHandler {
+            entries: route.entries,
+            etags,
+            body_hash: route.body_hash,
+            override_dirs: options.override_dirs().cloned().collect(),
+            max_response_size: options.max_response_size,
+            next_entry: options.round_robin.then(|| Arc::new(AtomicUsize::new(0))),
+        };
+        routes.push(EntryRoute {
+            method: route.method,
+            path: route.path,
+            rank: route.rank,
+            handler,
+        });
+    }
+    Ok(routes)
+}
+
+// the entry routes of a server built with ServerOptions::watch, managed as
+// rocket state so that they can be replaced while it runs
+#[derive(Clone)]
+pub struct EntryTable {
+    routes: Arc<RwLock<Arc<Vec<EntryRoute>>>>,
+    // of the HAR last loaded
+    entry_count: Arc<AtomicUsize>,
+}
+
+impl EntryTable {
+    // replaces the routes with those for `har`. requests already being
+    // handled finish with the entries they started with
+    pub fn reload(&self, har: &Har, options: &ServerOptions) -> Result<()> {
+        let routes = har_entry_routes(har, options)?;
+        *self.routes.write().unwrap() = Arc::new(routes);
+        self.entry_count.store(har.entries.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entry_count.load(Ordering::Relaxed)
+    }
+}
+
+// stands in for every entry route while watching, dispatching requests to
+// the EntryTable's current routes
+#[derive(Clone)]
+struct WatchedRoutes {
+    table: EntryTable,
+}
+
+#[rocket::async_trait]
+impl Handler for WatchedRoutes {
+    async fn handle<'r>(&self, req: &'r Request<'_>, mut data: Data<'r>) -> Outcome<'r> {
+        let routes = self.table.routes.read().unwrap().clone();
+        let segments: Vec<&str> = req.uri().path().segments().collect();
+        // routes for a single request body hash go first, as their ranks
+        // put them when mounted
+        let (body_hash_routes, other_routes): (Vec<&EntryRoute>, Vec<&EntryRoute>) = routes
+            .iter()
+            .filter(|route| route.method == req.method())
+            .partition(|route| route.rank.is_some());
+        for route in body_hash_routes.into_iter().chain(other_routes) {
+            if !route.matches(&segments, req.uri().query()) {
+                continue;
+            }
+            match route.handler.handle(req, data).await {
+                Outcome::Forward((forwarded, _)) => data = forwarded,
+                outcome => return outcome,
+            }
+        }
+        Outcome::Forward((data, Status::NotFound))
+    }
+}
+
+// how a request was handled, stashed in the request-local cache by the
+// handlers so that the request logger can report it
+#[derive(Clone, Copy)]
+enum MatchResult {
+    Unmatched,
+    Entry(usize),
+    Proxied,
+}
+
+struct RequestLogger {
+    format: RequestLogFormat,
+}
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Logger",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let result = req.local_cache(|| MatchResult::Unmatched);
+        let status = res.status().code;
+        match self.format {
+            RequestLogFormat::Text => {
+                let description = match result {
+                    MatchResult::Entry(index) => format!("matched HAR entry #{}", index),
+                    MatchResult::Proxied => "proxied upstream".to_string(),
+                    MatchResult::Unmatched => format!("{} no match", status),
+                };
+                println!("→ {} {} [{}]", req.method(), req.uri(), description);
+            }
+            RequestLogFormat::Json => {
+                let (result_name, entry) = match result {
+                    MatchResult::Entry(index) => ("matched", Some(*index)),
+                    MatchResult::Proxied => ("proxied", None),
+                    MatchResult::Unmatched => ("unmatched", None),
+                };
+                let line = serde_json::json!({
+                    "method": req.method().as_str(),
+                    "uri": req.uri().to_string(),
+                    "result": result_name,
+                    "entry": entry,
+                    "status": status,
+                });
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+// when a request arrived, stashed in the request-local cache by AccessLog
+struct RequestStart(Instant);
+
+// appends a line to the --access-log file for every request
+struct AccessLog {
+    file: Mutex<File>,
+    format: AccessLogFormat,
+}
+
+#[rocket::async_trait]
+impl Fairing for AccessLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Access Log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let latency = req.local_cache(|| RequestStart(Instant::now())).0.elapsed();
+        let result = req.local_cache(|| MatchResult::Unmatched);
+        let status = res.status().code;
+        // streamed bodies have no size until they've been sent
+        let size = res.body().preset_size();
+        let line = match self.format {
+            AccessLogFormat::Jsonl => {
+                let (result_name, entry) = match result {
+                    MatchResult::Entry(index) => ("matched", Some(*index)),
+                    MatchResult::Proxied => ("proxied", None),
+                    MatchResult::Unmatched => ("unmatched", None),
+                };
+                serde_json::json!({
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                    "method": req.method().as_str(),
+                    "uri": req.uri().to_string(),
+                    "result": result_name,
+                    "entry": entry,
+                    "status": status,
+                    "bytes": size,
+                    "latency_ms": latency.as_secs_f64() * 1000.0,
+                })
+                .to_string()
+            }
+            AccessLogFormat::Clf => {
+                let header = |name: &str| req.headers().get_one(name).unwrap_or("-").to_string();
+                // rocket doesn't expose the request's HTTP version
+                format!(
+                    "{} - - [{}] \"{} {} HTTP/1.1\" {} {} \"{}\" \"{}\"",
+                    req.client_ip().map_or("-".to_string(), |ip| ip.to_string()),
+                    chrono::Local::now().format("%d/%b/%Y:%H:%M:%S %z"),
+                    req.method(),
+                    req.uri(),
+                    status,
+                    size.map_or("-".to_string(), |size| size.to_string()),
+                    header("referer"),
+                    header("user-agent")
+                )
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = writeln!(file, "{}", line) {
+            warn!("failed to write to access log: {}", err);
+        }
+    }
+}
+
+// adds the --cors-origin headers to every response, replacing any that were
+// recorded
+struct CorsHeaders {
+    origin: String,
+    credentials: bool,
+}
+
+#[rocket::async_trait]
+impl Fairing for CorsHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS Headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, _req: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_raw_header("Access-Control-Allow-Origin", self.origin.clone());
+        if self.credentials {
+            res.set_raw_header("Access-Control-Allow-Credentials", "true");
+        }
+    }
+}
+
+// answers CORS preflight requests no other route handled, allowing any
+// method and whichever headers the request asks for. CorsHeaders adds the
+// allowed origin
+#[derive(Clone)]
+struct PreflightHandler;
+
+#[rocket::async_trait]
+impl Handler for PreflightHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, _data: Data<'r>) -> Outcome<'r> {
+        let mut res = Response::new();
+        res.set_status(Status::NoContent);
+        res.set_raw_header("Access-Control-Allow-Methods", PREFLIGHT_METHODS);
+        if let Some(headers) = req.headers().get_one("access-control-request-headers") {
+            res.set_raw_header("Access-Control-Allow-Headers", headers.to_string());
+        }
+        Outcome::Success(res)
+    }
 }
 
 #[derive(Clone)]
 struct ProxyHandler {
     proxy_url: reqwest::Url,
+    // requests whose path doesn't match are forwarded rather than proxied
+    path_pattern: Option<Regex>,
+    // lowercased names of request headers not to send to the proxy
+    strip_headers: HashSet<String>,
+    max_response_size: Option<u64>,
 }
 
 #[rocket::async_trait]
 impl Handler for ProxyHandler {
-    async fn handle<'r>(&self, req: &'r Request<'_>, _: Data<'r>) -> Outcome<'r> {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        if let Some(pattern) = &self.path_pattern {
+            if !pattern.is_match(req.uri().path().as_str()) {
+                return Outcome::Forward((data, Status::NotFound));
+            }
+        }
         let client = reqwest::Client::new();
         let method = match req.method() {
             Method::Get => reqwest::Method::GET,
@@ -126,54 +1337,626 @@ impl Handler for ProxyHandler {
             Method::Connect => reqwest::Method::CONNECT,
             Method::Patch => reqwest::Method::PATCH,
         };
+        let started_date_time = chrono::Utc::now().to_rfc3339();
+        let start = Instant::now();
         let mut proxy_url = self.proxy_url.clone();
         proxy_url.set_path(req.uri().path().as_str());
         if let Some(query) = req.uri().query().as_ref() {
             proxy_url.set_query(Some(query.as_str()));
         }
-        let proxy_req = client.request(method, proxy_url).build().unwrap();
-        let proxy_res = client.execute(proxy_req).await.unwrap();
+        // proxied requests are on behalf of the origin, so they carry (and
+        // update) the cookies matching the origin and the request's path
+        let config = req.rocket().state::<Config>().unwrap();
+        let mut proxy_req = client.request(method, proxy_url);
+        let req_path = req.uri().path().as_str();
+        let jar_cookie = config
+            .cookie_jar
+            .as_ref()
+            .and_then(|jar| jar.cookie_header(&config.har_origin_host, req_path));
+        for header in req.headers().iter() {
+            let name = header.name().as_str().to_ascii_lowercase();
+            // the host is the proxy's, and reqwest sets the content-length
+            // from the body it sends
+            let skipped = HOP_BY_HOP_HEADERS.contains(&name.as_str())
+                || self.strip_headers.contains(&name)
+                || name == "host"
+                || name == "content-length"
+                || (name == "cookie" && jar_cookie.is_some());
+            if !skipped {
+                proxy_req = proxy_req.header(header.name().as_str(), header.value());
+            }
+        }
+        if let Some(cookie) = jar_cookie {
+            proxy_req = proxy_req.header(reqwest::header::COOKIE, cookie);
+        }
+        // the whole body is passed along, however large
+        let req_body = match data.open(ByteUnit::max_value()).into_bytes().await {
+            Ok(body) => body.into_inner(),
+            Err(err) => {
+                warn!(
+                    "{}: failed to read request body to proxy: {:?}",
+                    req.uri(),
+                    err
+                );
+                return Outcome::Error(Status::BadRequest);
+            }
+        };
+        if !req_body.is_empty() {
+            proxy_req = proxy_req.body(req_body);
+        }
+        let mut proxy_res = client.execute(proxy_req.build().unwrap()).await.unwrap();
+        req.local_cache(|| MatchResult::Proxied);
         let mut res = Response::new();
         let status = Status::from_code(proxy_res.status().as_u16()).unwrap();
         res.set_status(status);
         for (name, value) in proxy_res.headers() {
             let name_clone = name.to_string();
             let value_clone = value.to_str().unwrap().to_string();
+            if let Some(jar) = &config.cookie_jar {
+                if name == reqwest::header::SET_COOKIE {
+                    jar.store(&config.har_origin_host, req_path, &value_clone);
+                }
+            }
+            let value_clone = if name == reqwest::header::SET_COOKIE && config.rewrite_cookies {
+                rewrite_set_cookie(&value_clone)
+            } else {
+                value_clone
+            };
             res.adjoin_raw_header(name_clone, value_clone);
         }
-        if let Ok(bytes) = proxy_res.bytes().await {
-            res.set_sized_body(bytes.len(), io::Cursor::new(bytes));
+        config.inject_headers(&mut res);
+        let mut body = Vec::new();
+        while let Ok(Some(chunk)) = proxy_res.chunk().await {
+            body.extend_from_slice(&chunk);
+            if let Some(limit) = self.max_response_size {
+                if body.len() as u64 > limit {
+                    warn!(
+                        "{}: proxied response body exceeds {} byte limit",
+                        req.uri(),
+                        limit
+                    );
+                    return Outcome::Error(Status::ServiceUnavailable);
+                }
+            }
+        }
+        if let Some(recorder) = &config.recorder {
+            recorder.record(Exchange {
+                started_date_time,
+                time: start.elapsed(),
+                method: req.method().as_str().to_string(),
+                uri: req.uri().to_string(),
+                req_headers: req
+                    .headers()
+                    .iter()
+                    .map(|header| (header.name().to_string(), header.value().to_string()))
+                    .collect(),
+                status: status.code,
+                status_text: status.reason_lossy().to_string(),
+                res_headers: proxy_res
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+                        (name.to_string(), value)
+                    })
+                    .collect(),
+                body: body.clone(),
+            });
         }
+        res.set_sized_body(body.len(), io::Cursor::new(body));
         Outcome::Success(res)
     }
 }
 
+// prints the port the OS assigned for `--port 0`, so callers (e.g. tests
+// running several servers in parallel) can find the server
+struct PortAnnouncer {
+    json: bool,
+}
+
+#[rocket::async_trait]
+impl Fairing for PortAnnouncer {
+    fn info(&self) -> Info {
+        Info {
+            name: "Port Announcer",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let port = rocket.config().port;
+        if self.json {
+            println!("{}", serde_json::json!({ "port": port }));
+        } else {
+            println!("port={}", port);
+        }
+    }
+}
+
+// writes entries recorded by --record-new once the server shuts down
+struct RecordingWriter;
+
+#[rocket::async_trait]
+impl Fairing for RecordingWriter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Recording Writer",
+            kind: Kind::Shutdown,
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        let Some(recorder) = rocket.state::<Config>().and_then(|c| c.recorder.as_ref()) else {
+            return;
+        };
+        if let Err(err) = recorder.write() {
+            warn!("failed to write recorded entries: {}", err);
+        }
+    }
+}
+
+// saves --cookie-jar once the server shuts down, rather than writing it on
+// the executor for every Set-Cookie
+struct CookieJarWriter;
+
+#[rocket::async_trait]
+impl Fairing for CookieJarWriter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Cookie Jar Writer",
+            kind: Kind::Shutdown,
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        let Some(jar) = rocket.state::<Config>().and_then(|c| c.cookie_jar.as_ref()) else {
+            return;
+        };
+        if let Err(err) = jar.save() {
+            warn!("failed to save cookie jar: {}", err);
+        }
+    }
+}
+
+// counts the requests being handled, to say how many a shutdown is waiting
+// for. a request counts as finished once its response is produced, so
+// streamed bodies (like server-sent events) and websocket upgrades still in
+// progress aren't counted, though rocket's grace period covers their
+// connections all the same
+#[derive(Default)]
+struct RequestDrain {
+    in_flight: AtomicUsize,
+}
+
+#[rocket::async_trait]
+impl Fairing for RequestDrain {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Drain",
+            kind: Kind::Request | Kind::Response | Kind::Shutdown,
+        }
+    }
+
+    async fn on_request(&self, _req: &mut Request<'_>, _data: &mut Data<'_>) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    async fn on_response<'r>(&self, _req: &'r Request<'_>, _res: &mut Response<'r>) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // rocket stops accepting connections, then runs this before giving the
+    // rest up to its grace period to finish
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        println!(
+            "Shutting down, waiting for {} requests",
+            self.in_flight.load(Ordering::Relaxed)
+        );
+    }
+}
+
+fn strip_body_hash_param(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|param| !param.starts_with(&format!("{}=", BODY_HASH_PARAM)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// treats a missing query string (`/path`) and an empty one (`/path?`) as
+// equivalent, since browsers and HAR recorders disagree on the trailing `?`
+pub fn normalize_query(query: Option<&str>) -> Option<&str> {
+    query.filter(|q| !q.is_empty())
+}
+
+// drops a Set-Cookie value's Domain and Secure attributes, and relaxes
+// SameSite=None (which browsers reject without Secure) to Lax, so that
+// browsers accept the cookie from harbinger over plain HTTP. without a
+// Domain, the cookie belongs to whichever host harbinger is reached by
+pub fn rewrite_set_cookie(value: &str) -> String {
+    let mut parts = value.split(';');
+    let mut rewritten = vec![parts.next().unwrap_or_default().trim().to_string()];
+    for attribute in parts.map(str::trim) {
+        let name = attribute.split('=').next().unwrap_or_default().trim();
+        if name.eq_ignore_ascii_case("domain") || name.eq_ignore_ascii_case("secure") {
+            continue;
+        }
+        if name.eq_ignore_ascii_case("samesite")
+            && attribute
+                .split_once('=')
+                .is_some_and(|(_, value)| value.trim().eq_ignore_ascii_case("none"))
+        {
+            rewritten.push("SameSite=Lax".to_string());
+            continue;
+        }
+        rewritten.push(attribute.to_string());
+    }
+    rewritten.join("; ")
+}
+
+// points absolute and protocol-relative URLs on the HAR's origin host at the
+// base URL instead
+fn rewrite_origin(body: &str, har_origin_host: &str, base_url: &reqwest::Url) -> String {
+    let base_origin = base_url.origin().ascii_serialization();
+    let base_authority = base_origin
+        .split_once("://")
+        .map_or(base_origin.as_str(), |(_, authority)| authority);
+    body.replace(&format!("https://{}", har_origin_host), &base_origin)
+        .replace(&format!("http://{}", har_origin_host), &base_origin)
+        .replace(
+            &format!("//{}", har_origin_host),
+            &format!("//{}", base_authority),
+        )
+}
+
+// form fields may be sent in any order, so they're compared as a map of
+// field names to their sorted values
+fn parse_form(body: &[u8]) -> HashMap<String, Vec<String>> {
+    let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, value) in form_urlencoded::parse(body) {
+        fields
+            .entry(name.into_owned())
+            .or_default()
+            .push(value.into_owned());
+    }
+    for values in fields.values_mut() {
+        values.sort();
+    }
+    fields
+}
+
+// form and JSON bodies are compared by value, so that field order and
+// whitespace don't matter. anything else must match byte for byte
+fn request_bodies_match(
+    recorded: Option<&[u8]>,
+    incoming: &[u8],
+    content_type: Option<&ContentType>,
+) -> bool {
+    let Some(recorded) = recorded else {
+        return false;
+    };
+    match content_type {
+        Some(ct) if ct.is_form() => parse_form(recorded) == parse_form(incoming),
+        Some(ct) if ct.is_json() => match (
+            serde_json::from_slice::<serde_json::Value>(recorded),
+            serde_json::from_slice::<serde_json::Value>(incoming),
+        ) {
+            (Ok(recorded), Ok(incoming)) => recorded == incoming,
+            _ => recorded == incoming,
+        },
+        _ => recorded == incoming,
+    }
+}
+
+// a strong validator for a response body, the same for identical bodies
+// across restarts
+fn body_etag(body: &[u8]) -> String {
+    format!("\"{:x}\"", Sha256::digest(body))
+}
+
+// whether an If-None-Match header value lists the etag. the comparison is
+// weak, so `W/` prefixes are ignored
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// the frames a WebSocket entry's server sent, with when to send them
+// relative to the first recorded frame. control frames aren't replayed
+fn ws_replay(entry: &Entry) -> Vec<(Duration, Message)> {
+    let messages = entry.ws_messages();
+    let Some(first) = messages.first() else {
+        return Vec::new();
+    };
+    messages
+        .iter()
+        .filter(|message| message.is_received())
+        .filter_map(|message| {
+            let frame = match message.opcode {
+                1 => Message::Text(message.data.clone()),
+                2 => Message::Binary(STANDARD.decode(&message.data).ok()?),
+                _ => return None,
+            };
+            let offset = Duration::try_from_secs_f64(message.time - first.time).unwrap_or_default();
+            Some((offset, frame))
+        })
+        .collect()
+}
+
+fn to_event(event: SseEvent) -> Event {
+    let mut sse = match event.data {
+        Some(data) => Event::data(data),
+        None => Event::empty(),
+    };
+    if let Some(name) = event.event {
+        sse = sse.event(name);
+    }
+    if let Some(id) = event.id {
+        sse = sse.id(id);
+    }
+    if let Some(retry) = event.retry {
+        sse = sse.with_retry(Duration::from_millis(retry));
+    }
+    sse
+}
+
+// the status for a body get_body couldn't load
+fn body_failure<'r>(err: anyhow::Error) -> Outcome<'r> {
+    warn!("entry failed to handle request: {}", err);
+    match err.downcast_ref::<HarbingerError>() {
+        Some(HarbingerError::ResponseTooLarge { .. }) => Outcome::Error(Status::ServiceUnavailable),
+        _ => Outcome::Error(Status::InternalServerError),
+    }
+}
+
 #[derive(Clone)]
 struct EntryHandler {
     entries: Vec<Entry>,
-    dump_path: Option<PathBuf>,
+    // the etags of the entries' recorded bodies, by entry index
+    etags: HashMap<usize, String>,
+    // set when every entry shares this request body hash, and requests name
+    // it with BODY_HASH_PARAM
+    body_hash: Option<String>,
+    // where to look for a file to serve instead of an entry's recorded body,
+    // in order, each laid out like a dump
+    override_dirs: Vec<PathBuf>,
+    max_response_size: Option<u64>,
+    // counts requests handled, to rotate through the entries that match
+    // them, or None to always serve the first match. shared by every clone
+    // of the handler, but not with other handlers
+    next_entry: Option<Arc<AtomicUsize>>,
 }
 
 impl EntryHandler {
-    fn get_body(&self, entry: &Entry) -> Result<Vec<u8>> {
-        if let Some(base_path) = &self.dump_path {
-            let override_path = entry.get_dump_path(base_path)?;
-            if override_path.exists() {
-                info!(
-                    "{} {}: loading body from file {}",
-                    entry.method()?,
-                    entry.uri()?,
-                    override_path.display()
+    fn check_response_size(&self, entry: &Entry, size: u64) -> Result<()> {
+        match self.max_response_size {
+            Some(limit) if size > limit => Err(HarbingerError::ResponseTooLarge {
+                url: entry.url().to_string(),
+                size,
+                limit,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    // the body to serve and its etag. override files may change while the
+    // server runs, so their etags are computed as they're read
+    fn get_body(&self, entry: &Entry) -> Result<(Vec<u8>, String)> {
+        if let Some(override_path) = find_override(entry, &self.override_dirs)? {
+            self.check_response_size(entry, override_path.metadata()?.len())?;
+            info!(
+                "{}: loading body from file {}",
+                entry,
+                override_path.display()
+            );
+            let body = std::fs::read(override_path)?;
+            let etag = body_etag(&body);
+            return Ok((body, etag));
+        }
+        info!("{}: loading body from HAR", entry);
+        let body = entry.res_body().unwrap_or(vec![]);
+        self.check_response_size(entry, body.len() as u64)?;
+        Ok((body, self.etags[&entry.index()].clone()))
+    }
+
+    // entries at the request's path whose query matches exactly, or failing
+    // that, those recorded without a query string. paths are compared
+    // decoded, since routes with wildcard segments (see route_segment) match
+    // more than one path
+    fn find_entries(&self, req_path: uri::Path<'_>, req_query: Option<&str>) -> Vec<&Entry> {
+        let req_segments: Vec<&str> = req_path.segments().collect();
+        let at_path: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| {
+                let uri = entry.uri().unwrap();
+                let segments: Vec<&str> = uri.path().segments().collect();
+                // the request's path may start with the entry's hostname
+                req_segments.ends_with(&segments)
+            })
+            .collect();
+        let entry_query_is = |entry: &Entry, query: Option<&str>| {
+            let uri = entry.uri().unwrap();
+            normalize_query(uri.query().map(|q| q.as_str())) == query
+        };
+        let exact_matches: Vec<&Entry> = at_path
+            .iter()
+            .copied()
+            .filter(|entry| entry_query_is(entry, req_query))
+            .collect();
+        if !exact_matches.is_empty() {
+            return exact_matches;
+        }
+        at_path
+            .into_iter()
+            .filter(|entry| entry_query_is(entry, None))
+            .collect()
+    }
+
+    // prefers the candidate whose recorded request body matches the incoming
+    // one, falling back to the first candidate if the body is too large
+    async fn find_entry_by_body<'a>(
+        &self,
+        config: &Config,
+        req: &Request<'_>,
+        data: Data<'_>,
+        candidates: &[&'a Entry],
+    ) -> &'a Entry {
+        let limit = config.request_body_limit;
+        match data.open(limit.bytes()).into_bytes().await {
+            Ok(body) if body.is_complete() => candidates
+                .iter()
+                .find(|entry| request_bodies_match(entry.req_body(), &body, req.content_type()))
+                .copied()
+                .unwrap_or(candidates[0]),
+            Ok(_) => {
+                warn!(
+                    "{} {}: request body exceeds {} bytes, skipping body matching",
+                    req.method(),
+                    req.uri(),
+                    limit
                 );
-                return std::fs::read(override_path).map_err(|err| err.into());
+                candidates[0]
+            }
+            Err(err) => {
+                warn!("failed to read request body: {:?}", err);
+                candidates[0]
             }
         }
+    }
+
+    // replays the frames the server sent on a recorded WebSocket at their
+    // recorded times, discarding any the client sends. the connection stays
+    // open until the client closes it
+    async fn replay_websocket<'r>(&self, req: &'r Request<'_>, entry: &Entry) -> Outcome<'r> {
+        let Some(ws) = WebSocket::from_request(req).await.succeeded() else {
+            warn!("{}: request isn't a WebSocket handshake", entry);
+            return Outcome::Error(Status::BadRequest);
+        };
+        let frames = ws_replay(entry);
+        info!("{}: replaying {} WebSocket frames", entry, frames.len());
+        let channel = ws.channel(move |mut stream| {
+            Box::pin(async move {
+                let start = Instant::now();
+                for (offset, frame) in frames {
+                    tokio::time::sleep(offset.saturating_sub(start.elapsed())).await;
+                    stream.send(frame).await?;
+                }
+                while let Some(message) = stream.next().await {
+                    message?;
+                }
+                Ok(())
+            })
+        });
+        Outcome::from(req, channel)
+    }
+
+    // sends a recorded event stream's events one at a time rather than all
+    // at once. the body may come from a dumped file, like any other
+    fn stream_events<'r>(
+        &self,
+        config: &Config,
+        req: &'r Request<'_>,
+        entry: &Entry,
+    ) -> Outcome<'r> {
+        let events = match self.get_body(entry) {
+            Ok((body, _)) => parse_sse_events(&String::from_utf8_lossy(&body)),
+            Err(err) => return body_failure(err),
+        };
+        let delay = config.sse_delay(entry, events.len());
         info!(
-            "{} {}: loading body from HAR",
-            entry.method()?,
-            entry.uri()?
+            "{}: streaming {} events, {}ms apart",
+            entry,
+            events.len(),
+            delay.as_millis()
         );
-        Ok(entry.res_body().unwrap_or(vec![]))
+        let stream = EventStream! {
+            for (i, event) in events.into_iter().enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(delay).await;
+                }
+                yield to_event(event);
+            }
+        };
+        Outcome::from(req, stream.heartbeat(None))
+    }
+
+    // answers with 304 Not Modified rather than the body when the request's
+    // If-None-Match lists the body's etag. only successful GET and HEAD
+    // responses are revalidated
+    fn respond<'r>(&self, config: &Config, req: &Request<'_>, entry: &Entry) -> Outcome<'r> {
+        let mut res = Response::new();
+        for (name, value) in entry.res_headers() {
+            if config.header_filter.is_stripped(name) {
+                continue;
+            }
+            let normalized_name = name.to_ascii_lowercase();
+            if normalized_name == "cache-control" && config.reset_cache_control {
+                continue;
+            }
+            if normalized_name == "set-cookie" {
+                if let (Some(jar), Ok(hostname), Ok(uri)) =
+                    (&config.cookie_jar, entry.hostname(), entry.uri())
+                {
+                    jar.store(&hostname, uri.path().as_str(), value);
+                }
+            }
+
+            // handle Location headers for redirects
+            let rewritten =
+                if normalized_name == "location" && config.rewrite_location && config.serve_ui {
+                    entry.redirect_location(&config.har_origin_host)
+                } else if normalized_name == "set-cookie" && config.rewrite_cookies {
+                    Some(rewrite_set_cookie(value))
+                } else {
+                    None
+                };
+            // adjoined rather than set, so that repeated headers like
+            // set-cookie and link are all sent
+            res.adjoin_raw_header(
+                name.to_string(),
+                rewritten.unwrap_or_else(|| value.to_string()),
+            );
+        }
+        config.inject_headers(&mut res);
+        if let Some(csp) = &config.csp {
+            if config.csp_report_only {
+                res.set_raw_header("content-security-policy-report-only", csp.clone());
+                res.set_raw_header(
+                    "reporting-endpoints",
+                    format!("{}=\"{}\"", CSP_REPORT_ENDPOINT, CSP_REPORT_PATH),
+                );
+            } else {
+                res.set_raw_header("content-security-policy", csp.clone());
+            }
+        }
+        if config.reset_cache_control {
+            res.set_raw_header("cache-control", "no-store");
+        }
+        let status = Status::new(entry.status() as u16);
+        match self.get_body(entry) {
+            Ok((body, etag)) => {
+                let not_modified = matches!(req.method(), Method::Get | Method::Head)
+                    && status.class() == StatusClass::Success
+                    && req
+                        .headers()
+                        .get("if-none-match")
+                        .any(|value| etag_matches(value, &etag));
+                res.set_raw_header("etag", etag);
+                if not_modified {
+                    res.set_status(Status::NotModified);
+                    return Outcome::Success(res);
+                }
+                let body = config.rewrite_body(body);
+                res.set_sized_body(None, io::Cursor::new(body))
+            }
+            Err(err) => return body_failure(err),
+        }
+        res.set_status(status);
+        Outcome::Success(res)
     }
 }
 
@@ -181,45 +1964,409 @@ impl EntryHandler {
 impl Handler for EntryHandler {
     // handler for a group of entries that share the same path
     async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
-        for entry in &self.entries {
-            if req.uri().query() == entry.uri().unwrap().query() {
+        let mut req_query = req.uri().query().map(|q| q.as_str().to_string());
+        if self.body_hash.is_some() {
+            req_query = req_query.map(|query| strip_body_hash_param(&query));
+        }
+        let candidates = self.find_entries(req.uri().path(), normalize_query(req_query.as_deref()));
+        if candidates.is_empty() {
+            return Outcome::Forward((data, Status::NotFound));
+        }
+        // build_server always manages the config
+        let config = req.rocket().state::<Config>().unwrap();
+        // held until the response is built
+        let _permit = match config
+            .concurrency_limit
+            .as_ref()
+            .map(Semaphore::try_acquire)
+        {
+            Some(Err(_)) => {
                 let mut res = Response::new();
-                for (name, value) in entry.res_headers() {
-                    let normalized_name = name.to_ascii_lowercase();
-                    if UNFORWARDED_HEADERS.contains(&normalized_name.as_str()) {
-                        continue;
-                    }
-
-                    // handle Location headers for redirects
-                    if normalized_name == "location" {
-                        let hostname = entry.hostname().unwrap();
-                        let new_location = if value.starts_with('/') {
-                            format!("/{}{}", hostname, value)
-                        } else {
-                            format!("/{}/{}", hostname, value)
-                        };
-                        res.set_raw_header(name.to_string(), new_location);
-                    } else {
-                        res.set_raw_header(name.to_string(), value.to_string());
-                    }
-                }
-                let csp_components = [
-                    "base-uri 'self'",
-                    "default-src * 'unsafe-inline' 'unsafe-eval'",
-                    "worker-src 'self'",
-                ];
-                res.set_raw_header("content-security-policy", csp_components.join("; "));
-                match self.get_body(entry) {
-                    Ok(body) => res.set_sized_body(None, io::Cursor::new(body)),
-                    Err(err) => {
-                        warn!("entry failed to handle request: {:?}", err);
-                        return Outcome::Failure(Status::InternalServerError);
-                    }
-                }
-                res.set_status(rocket::http::Status::new(entry.status() as u16));
+                res.set_status(Status::ServiceUnavailable);
+                res.set_raw_header("retry-after", "1");
                 return Outcome::Success(res);
             }
+            permit => permit,
+        };
+        let needs_body_match =
+            self.body_hash.is_none() && candidates.iter().any(|entry| entry.req_body().is_some());
+        // only the entries that passed the query match take part in the
+        // rotation. entries matched by body aren't rotated
+        let entry = if needs_body_match {
+            self.find_entry_by_body(config, req, data, &candidates)
+                .await
+        } else if let Some(next_entry) = &self.next_entry {
+            candidates[next_entry.fetch_add(1, Ordering::Relaxed) % candidates.len()]
+        } else {
+            candidates[0]
+        };
+
+        req.local_cache(|| MatchResult::Entry(entry.index()));
+        let delay = config.response_delay(entry.url()) + config.simulated_latency(entry);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        if entry.is_websocket() {
+            return self.replay_websocket(req, entry).await;
+        }
+        if entry.is_event_stream() {
+            return self.stream_events(config, req, entry);
+        }
+        self.respond(config, req, entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::asynchronous::Client;
+    use serde_json::json;
+
+    // a recorded entry with a text body
+    fn entry(
+        method: &str,
+        url: &str,
+        status: i64,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> serde_json::Value {
+        let headers: Vec<serde_json::Value> = headers
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        json!({
+            "startedDateTime": "2023-06-01T12:00:00.000Z",
+            "time": 12.0,
+            "request": {
+                "method": method,
+                "url": url,
+                "httpVersion": "HTTP/1.1",
+                "cookies": [],
+                "headers": [],
+                "queryString": [],
+                "headersSize": -1,
+                "bodySize": 0
+            },
+            "response": {
+                "status": status,
+                "statusText": "",
+                "httpVersion": "HTTP/1.1",
+                "cookies": [],
+                "headers": headers,
+                "content": { "size": body.len(), "mimeType": "text/plain", "text": body },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": body.len()
+            },
+            "cache": {},
+            "timings": { "send": 0.0, "wait": 12.0, "receive": 0.0 }
+        })
+    }
+
+    fn har(entries: Vec<serde_json::Value>) -> Har {
+        Har::from_json_value(json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "harbinger tests", "version": "1.0" },
+                "entries": entries
+            }
+        }))
+        .unwrap()
+    }
+
+    async fn client(har: &Har, options: &ServerOptions) -> Client {
+        Client::tracked(build_server(har, options).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn route_path(url: &str, origin_host: Option<&str>) -> String {
+        let entry_uri = uri::Uri::parse::<uri::Reference>(url).unwrap();
+        get_entry_route_path(&entry_uri, origin_host).unwrap()
+    }
+
+    fn segment(raw: &str) -> String {
+        route_segment(RawStr::new(raw))
+    }
+
+    #[test]
+    fn route_segment_decodes_route_characters() {
+        assert_eq!(segment("app.js"), "app.js");
+        assert_eq!(segment("%40admin"), "@admin");
+        assert_eq!(segment("a%2Bb"), "a+b");
+        assert_eq!(segment("%7Euser"), "~user");
+        assert_eq!(segment("k%3Dv%3Bx"), "k=v;x");
+        assert_eq!(segment("(1)%2C'2'"), "(1),'2'");
+        assert_eq!(segment(""), "");
+    }
+
+    #[test]
+    fn route_segment_falls_back_to_a_wildcard() {
+        // characters a route can't contain
+        assert_eq!(segment("a%2Fb"), "<_>");
+        assert_eq!(segment("a%20b"), "<_>");
+        assert_eq!(segment("%3Cid%3E"), "<_>");
+        assert_eq!(segment("caf%C3%A9"), "<_>");
+        assert_eq!(segment("50%25"), "<_>");
+        assert_eq!(segment("%3Fq"), "<_>");
+        // and segments that don't decode to UTF-8
+        assert_eq!(segment("%FF"), "<_>");
+    }
+
+    #[test]
+    fn route_path_on_origin_host() {
+        let origin = Some("example.com");
+        assert_eq!(route_path("https://example.com/a/b.js", origin), "/a/b.js");
+        assert_eq!(route_path("https://example.com/", origin), "/");
+        assert_eq!(route_path("https://example.com", origin), "/");
+        assert_eq!(route_path("https://example.com/a/", origin), "/a/");
+        assert_eq!(route_path("https://EXAMPLE.com:8443/a", origin), "/a");
+    }
+
+    #[test]
+    fn route_path_on_other_hosts() {
+        let origin = Some("example.com");
+        assert_eq!(
+            route_path("https://cdn.net/lib.js", origin),
+            "/cdn.net/lib.js"
+        );
+        assert_eq!(route_path("https://cdn.net/", origin), "/cdn.net/");
+        assert_eq!(
+            route_path("https://cdn.net:8080/lib.js", origin),
+            "/cdn.net/lib.js"
+        );
+    }
+
+    #[test]
+    fn route_path_on_origin_subdomain() {
+        assert_eq!(
+            route_path("https://api.example.com/v1/user", Some("example.com")),
+            "/api.example.com/v1/user"
+        );
+    }
+
+    #[test]
+    fn route_path_without_query_or_fragment() {
+        let origin = Some("example.com");
+        assert_eq!(
+            route_path("https://example.com/search?q=1&page=2", origin),
+            "/search"
+        );
+        assert_eq!(
+            route_path("https://example.com/page#anchor", origin),
+            "/page"
+        );
+        assert_eq!(
+            route_path("https://cdn.net/a?v=3#top", origin),
+            "/cdn.net/a"
+        );
+    }
+
+    #[test]
+    fn route_path_with_percent_encoded_segments() {
+        let origin = Some("example.com");
+        assert_eq!(
+            route_path("https://example.com/%40admin/users", origin),
+            "/@admin/users"
+        );
+        assert_eq!(route_path("https://example.com/a%20b/c", origin), "/<_>/c");
+        assert_eq!(route_path("https://example.com/a%2Fb/c", origin), "/<_>/c");
+    }
+
+    #[test]
+    fn route_path_without_origin_host() {
+        assert_eq!(route_path("https://example.com/a/b.js", None), "/a/b.js");
+        assert_eq!(route_path("https://cdn.net/lib.js?v=1", None), "/lib.js");
+    }
+
+    #[test]
+    fn route_path_needs_a_host() {
+        let entry_uri = uri::Uri::parse::<uri::Reference>("/relative/path").unwrap();
+        assert!(get_entry_route_path(&entry_uri, Some("example.com")).is_err());
+    }
+
+    // a HAR recorded on example.com with a redirect at `url` to `location`
+    fn redirect_har(url: &str, status: i64, location: &str) -> Har {
+        har(vec![
+            entry("GET", "https://example.com/", 200, &[], "home"),
+            entry("GET", url, status, &[("Location", location)], ""),
+        ])
+    }
+
+    async fn served_location(har: &Har, path: &str, options: &ServerOptions) -> Option<String> {
+        let client = client(har, options).await;
+        let response = client.get(path).dispatch().await;
+        response.headers().get_one("location").map(str::to_string)
+    }
+
+    #[rocket::async_test]
+    async fn rewrites_absolute_redirect_locations() {
+        let options = ServerOptions::default();
+        let har = redirect_har(
+            "https://example.com/old",
+            301,
+            "https://example.com/new?a=1",
+        );
+        assert_eq!(
+            served_location(&har, "/old", &options).await.as_deref(),
+            Some("/new?a=1")
+        );
+        let har = redirect_har(
+            "https://example.com/login",
+            302,
+            "https://auth.example.com/sso",
+        );
+        assert_eq!(
+            served_location(&har, "/login", &options).await.as_deref(),
+            Some("/auth.example.com/sso")
+        );
+    }
+
+    #[rocket::async_test]
+    async fn rewrites_relative_redirect_locations() {
+        let options = ServerOptions::default();
+        let har = redirect_har("https://example.com/a/b/old", 307, "../new");
+        assert_eq!(
+            served_location(&har, "/a/b/old", &options).await.as_deref(),
+            Some("/a/new")
+        );
+        let har = redirect_har("https://example.com/old", 308, "/new");
+        assert_eq!(
+            served_location(&har, "/old", &options).await.as_deref(),
+            Some("/new")
+        );
+        // relative to an entry on another host, so it stays on that host
+        let har = redirect_har("https://cdn.net/v1/lib.js", 302, "/v2/lib.js");
+        assert_eq!(
+            served_location(&har, "/cdn.net/v1/lib.js", &options)
+                .await
+                .as_deref(),
+            Some("/cdn.net/v2/lib.js")
+        );
+    }
+
+    #[rocket::async_test]
+    async fn leaves_other_locations_alone() {
+        // only redirects are rewritten
+        let har = redirect_har(
+            "https://example.com/created",
+            201,
+            "https://example.com/new",
+        );
+        assert_eq!(
+            served_location(&har, "/created", &ServerOptions::default())
+                .await
+                .as_deref(),
+            Some("https://example.com/new")
+        );
+        let har = redirect_har("https://example.com/old", 302, "https://example.com/new");
+        let options = ServerOptions {
+            rewrite_location: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            served_location(&har, "/old", &options).await.as_deref(),
+            Some("https://example.com/new")
+        );
+    }
+
+    #[rocket::async_test]
+    async fn serves_entries_recorded_with_fragments() {
+        // SPA hash routes can contain characters rocket's URI parser
+        // rejects, which used to keep the entry from being routed at all
+        let har = har(vec![
+            entry("GET", "https://example.com/", 200, &[], "home"),
+            entry("GET", "https://example.com/page#anchor", 200, &[], "page"),
+            entry(
+                "GET",
+                "https://example.com/app#/users/{id}?tab=a b",
+                200,
+                &[],
+                "app",
+            ),
+        ]);
+        let client = client(&har, &ServerOptions::default()).await;
+        for (path, body) in [("/page", "page"), ("/app", "app")] {
+            let response = client.get(path).dispatch().await;
+            assert_eq!(response.status(), Status::Ok, "{}", path);
+            assert!(response.headers().get_one("location").is_none());
+            assert_eq!(response.into_string().await.as_deref(), Some(body));
+        }
+    }
+
+    #[rocket::async_test]
+    async fn serves_entries_with_percent_encoded_paths() {
+        let har = har(vec![
+            entry(
+                "GET",
+                "https://example.com/api/users/%40admin/profile",
+                200,
+                &[],
+                "admin",
+            ),
+            // served through a wildcard route, since `/` can't be part of a
+            // route segment
+            entry(
+                "GET",
+                "https://example.com/files/a%2Fb",
+                200,
+                &[],
+                "slashed",
+            ),
+        ]);
+        let client = client(&har, &ServerOptions::default()).await;
+        for (path, body) in [
+            ("/api/users/%40admin/profile", "admin"),
+            ("/api/users/@admin/profile", "admin"),
+            ("/files/a%2Fb", "slashed"),
+            ("/files/a%2fb", "slashed"),
+        ] {
+            let response = client.get(path).dispatch().await;
+            assert_eq!(response.status(), Status::Ok, "{}", path);
+            assert_eq!(
+                response.into_string().await.as_deref(),
+                Some(body),
+                "{}",
+                path
+            );
         }
-        Outcome::Forward(data)
+        // the wildcard route matches, but EntryHandler compares the decoded
+        // paths
+        let response = client.get("/files/a%2Fc").dispatch().await;
+        assert_ne!(response.into_string().await.as_deref(), Some("slashed"));
+    }
+
+    #[rocket::async_test]
+    async fn health_reports_reloaded_entry_count() {
+        let options = ServerOptions {
+            watch: true,
+            ..Default::default()
+        };
+        let client = client(
+            &har(vec![entry("GET", "https://example.com/", 200, &[], "home")]),
+            &options,
+        )
+        .await;
+        let client = &client;
+        let health = move || async move {
+            let response = client.get("/harbinger/health").dispatch().await;
+            let body = response.into_string().await.unwrap();
+            serde_json::from_str::<serde_json::Value>(&body).unwrap()
+        };
+        let before = health().await;
+        assert_eq!(before["entries"], 1);
+        assert_eq!(before["origin_host"], "example.com");
+        assert!(before["uptime_secs"].is_u64());
+        assert_eq!(before["uptime_secs"], before["uptime_seconds"]);
+
+        let reloaded = har(vec![
+            entry("GET", "https://example.com/", 200, &[], "home"),
+            entry("GET", "https://example.com/new", 200, &[], "new"),
+        ]);
+        let table = client.rocket().state::<EntryTable>().unwrap();
+        table.reload(&reloaded, &options).unwrap();
+        assert_eq!(health().await["entries"], 2);
+        let response = client.get("/new").dispatch().await;
+        assert_eq!(response.into_string().await.as_deref(), Some("new"));
     }
 }
@@ -0,0 +1,222 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::har::{Entry, EntrySort, Har, PartyScope};
+
+const WATERFALL_WIDTH: usize = 40;
+const SLOWEST_ENTRY_COUNT: usize = 10;
+
+// how `stats` prints its summary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum StatsFormat {
+    // readable text, followed by the party breakdown and waterfall
+    Text,
+    // only the summary, as JSON
+    Json,
+}
+
+#[derive(Serialize)]
+struct EntrySummary {
+    method: String,
+    url: String,
+    status: i64,
+    wait_ms: Option<u64>,
+}
+
+impl From<&Entry> for EntrySummary {
+    fn from(entry: &Entry) -> Self {
+        EntrySummary {
+            method: entry
+                .method()
+                .map_or("???".to_string(), |method| method.to_string()),
+            url: entry.url().to_string(),
+            status: entry.status(),
+            wait_ms: entry.wait_ms(),
+        }
+    }
+}
+
+// an overview of what a HAR contains, for getting to know it before dumping
+// or serving it
+#[derive(Serialize)]
+pub struct HarSummary {
+    entries: usize,
+    unique_urls: usize,
+    methods: BTreeMap<String, usize>,
+    // keyed by MIME type, with responses that have none under "(none)"
+    content_types: BTreeMap<String, usize>,
+    // response body sizes as sent and once decoded, counting only the
+    // entries that recorded them
+    compressed_bytes: u64,
+    uncompressed_bytes: u64,
+    // entries that waited longest for a response, longest first
+    slowest: Vec<EntrySummary>,
+    // entries with a 4xx or 5xx status
+    errors: Vec<EntrySummary>,
+    no_body: Vec<EntrySummary>,
+}
+
+impl HarSummary {
+    pub fn new(har: &Har) -> Self {
+        let mut methods = BTreeMap::new();
+        let mut content_types = BTreeMap::new();
+        for entry in &har.entries {
+            let method = entry
+                .method()
+                .map_or("???".to_string(), |method| method.to_string());
+            *methods.entry(method).or_insert(0) += 1;
+            let content_type = entry.content_type().unwrap_or("(none)").to_string();
+            *content_types.entry(content_type).or_insert(0) += 1;
+        }
+        let mut by_wait: Vec<&Entry> = har
+            .entries
+            .iter()
+            .filter(|entry| entry.wait_ms().is_some())
+            .collect();
+        by_wait.sort_by_key(|entry| Reverse(entry.wait_ms()));
+        HarSummary {
+            entries: har.entries.len(),
+            unique_urls: har
+                .entries
+                .iter()
+                .map(Entry::url)
+                .collect::<HashSet<_>>()
+                .len(),
+            methods,
+            content_types,
+            compressed_bytes: har.entries.iter().filter_map(Entry::transfer_size).sum(),
+            uncompressed_bytes: har.entries.iter().filter_map(Entry::content_size).sum(),
+            slowest: by_wait
+                .into_iter()
+                .take(SLOWEST_ENTRY_COUNT)
+                .map(EntrySummary::from)
+                .collect(),
+            errors: har
+                .entries
+                .iter()
+                .filter(|entry| entry.status() >= 400)
+                .map(EntrySummary::from)
+                .collect(),
+            no_body: har
+                .entries
+                .iter()
+                .filter(|entry| !entry.res_body().is_some_and(|body| !body.is_empty()))
+                .map(EntrySummary::from)
+                .collect(),
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "entries: {} ({} unique URLs)",
+            self.entries, self.unique_urls
+        );
+        println!("methods:");
+        for (method, count) in &self.methods {
+            println!("  {:<7} {}", method, count);
+        }
+        println!("content types:");
+        for (content_type, count) in &self.content_types {
+            println!("  {:<40} {}", content_type, count);
+        }
+        println!(
+            "response bytes: {} compressed, {} uncompressed",
+            self.compressed_bytes, self.uncompressed_bytes
+        );
+        print_entry_list("slowest responses", &self.slowest);
+        print_entry_list("4xx/5xx responses", &self.errors);
+        print_entry_list("responses without a body", &self.no_body);
+    }
+}
+
+fn print_entry_list(title: &str, entries: &[EntrySummary]) {
+    println!("{}:", title);
+    if entries.is_empty() {
+        println!("  (none)");
+    }
+    for entry in entries {
+        let wait = entry
+            .wait_ms
+            .map_or("-".to_string(), |wait| format!("{}ms", wait));
+        println!(
+            "  {:>8} {:<7} {} {}",
+            wait, entry.method, entry.status, entry.url
+        );
+    }
+}
+
+// prints a devtools-style waterfall: when each request started relative to
+// the first, and a bar spanning its duration. rows are in start order unless
+// `sort` is given
+pub fn print_waterfall(har: &Har, sort: Option<EntrySort>) {
+    let mut timeline = har.response_timeline();
+    if let Some(sort) = sort {
+        let elapsed: HashMap<usize, f64> = timeline
+            .iter()
+            .map(|(elapsed, entry)| (entry.index(), *elapsed))
+            .collect();
+        let mut entries: Vec<&Entry> = timeline.iter().map(|(_, entry)| *entry).collect();
+        sort.sort(&mut entries);
+        timeline = entries
+            .into_iter()
+            .map(|entry| (elapsed[&entry.index()], entry))
+            .collect();
+    }
+    let end = timeline
+        .iter()
+        .map(|(elapsed, entry)| elapsed + entry.time() / 1000.0)
+        .fold(0.0, f64::max);
+    let scale = |seconds: f64| {
+        if end > 0.0 {
+            ((seconds / end) * WATERFALL_WIDTH as f64).round() as usize
+        } else {
+            0
+        }
+    };
+    for (elapsed, entry) in timeline {
+        let offset = scale(elapsed).min(WATERFALL_WIDTH - 1);
+        let length = scale(entry.time() / 1000.0).clamp(1, WATERFALL_WIDTH - offset);
+        let bar = format!("{}{}", " ".repeat(offset), "█".repeat(length));
+        let method = entry
+            .method()
+            .map_or("???".to_string(), |method| method.to_string());
+        println!(
+            "{:>8.3}s |{:<width$}| {:<7} {} {}",
+            elapsed,
+            bar,
+            method,
+            entry.status(),
+            entry.url(),
+            width = WATERFALL_WIDTH
+        );
+    }
+}
+
+// prints entry counts and response body sizes for first- and third-party
+// hosts
+pub fn print_party_summary(har: &Har, scope: PartyScope) -> Result<()> {
+    let origin_host = har.origin_host()?;
+    let (mut first_party, mut third_party) = ((0, 0), (0, 0));
+    for entry in &har.entries {
+        let totals = if entry.is_third_party_in(&origin_host, scope) {
+            &mut third_party
+        } else {
+            &mut first_party
+        };
+        totals.0 += 1;
+        totals.1 += entry.res_body().map_or(0, |body| body.len());
+    }
+    println!("origin: {}", origin_host);
+    println!(
+        "first-party: {} entries, {} bytes",
+        first_party.0, first_party.1
+    );
+    println!(
+        "third-party: {} entries, {} bytes",
+        third_party.0, third_party.1
+    );
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use anyhow::bail;
+use rocket::config::LogLevel;
+use rocket::http::{uri, Method};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::har::Har;
+use crate::server::{
+    build_server, get_entry_route_path, normalize_query, ServerOptions, INTERNAL_ROUTE_PATHS,
+};
+
+#[derive(Default)]
+pub struct ValidateReport {
+    pub valid: usize,
+    pub invalid: usize,
+    // problems that keep entries from being served, or the server from
+    // starting
+    pub invalid_uris: Vec<String>,
+    pub invalid_methods: Vec<String>,
+    // entries at the paths of harbinger's own routes
+    pub shadowed: Vec<String>,
+    pub server_errors: Vec<String>,
+    // servable, but possibly not what was intended
+    pub missing_bodies: Vec<String>,
+    // entries sharing a method, route and query with an earlier one
+    pub duplicates: Vec<String>,
+}
+
+impl ValidateReport {
+    pub fn is_servable(&self) -> bool {
+        self.invalid_uris.is_empty()
+            && self.invalid_methods.is_empty()
+            && self.shadowed.is_empty()
+            && self.server_errors.is_empty()
+    }
+
+    pub fn print(&self) {
+        for (label, problems) in [
+            ("invalid URI", &self.invalid_uris),
+            ("invalid method", &self.invalid_methods),
+            ("shadowed", &self.shadowed),
+            ("server error", &self.server_errors),
+            ("warning: missing body", &self.missing_bodies),
+            ("warning: duplicate", &self.duplicates),
+        ] {
+            for problem in problems {
+                println!("{}: {}", label, problem);
+            }
+        }
+        println!(
+            "{} valid entries, {} invalid, {} missing bodies, {} duplicates, {} shadowed",
+            self.valid,
+            self.invalid,
+            self.missing_bodies.len(),
+            self.duplicates.len(),
+            self.shadowed.len()
+        );
+    }
+}
+
+// statuses whose responses never have a body
+fn expects_body(status: i64) -> bool {
+    !matches!(status, 101 | 204 | 205 | 304) && !(300..400).contains(&status)
+}
+
+// goes through the same steps serve does before it starts listening,
+// reporting every problem rather than stopping at the first
+pub async fn validate(har: &Har) -> ValidateReport {
+    let mut report = ValidateReport::default();
+    let origin_host = match har.origin_host() {
+        Ok(host) => host,
+        Err(err) => {
+            report
+                .server_errors
+                .push(format!("couldn't determine origin host: {}", err));
+            return report;
+        }
+    };
+
+    // entries with the same method, route, decoded path and query, by their
+    // request body. entries whose paths only differ in segments a route
+    // can't contain share a route, but not their requests
+    let mut routes: HashMap<_, Vec<(usize, Option<&[u8]>)>> = HashMap::new();
+    let mut invalid = BTreeSet::new();
+    for entry in har.entries.iter().filter(|entry| !entry.is_data_uri()) {
+        let method = match entry.method() {
+            Ok(method) => Some(method),
+            Err(err) => {
+                report
+                    .invalid_methods
+                    .push(format!("entry #{}: {}", entry.index(), err));
+                invalid.insert(entry.index());
+                None
+            }
+        };
+        let route = entry.uri().and_then(|uri| {
+            let path = get_entry_route_path(&uri, Some(&origin_host))?;
+            if let Err(err) = uri::Origin::parse_route(&path) {
+                bail!("invalid route path {}: {}", path, err);
+            }
+            let segments: Vec<String> = uri.path().segments().map(str::to_string).collect();
+            let query = normalize_query(uri.query().map(|q| q.as_str())).map(str::to_string);
+            Ok((path, segments, query))
+        });
+        let (path, segments, query) = match route {
+            Ok(route) => route,
+            Err(err) => {
+                report
+                    .invalid_uris
+                    .push(format!("entry #{}: {}", entry.index(), err));
+                invalid.insert(entry.index());
+                continue;
+            }
+        };
+        if expects_body(entry.status()) && !entry.res_body().is_some_and(|body| !body.is_empty()) {
+            report
+                .missing_bodies
+                .push(format!("entry #{}: {}", entry.index(), entry));
+        }
+        let Some(method) = method else {
+            continue;
+        };
+        if method == Method::Get && INTERNAL_ROUTE_PATHS.contains(&path.as_str()) {
+            report.shadowed.push(format!(
+                "entry #{}: {} {} is served by harbinger itself",
+                entry.index(),
+                method,
+                path
+            ));
+            invalid.insert(entry.index());
+        }
+        routes
+            .entry((method, path, segments, query))
+            .or_default()
+            .push((entry.index(), entry.req_body()));
+    }
+
+    let mut duplicates = Vec::new();
+    for ((method, path, _, _), entries) in &routes {
+        let (first, _) = entries[0];
+        for &(index, body) in &entries[1..] {
+            let same_body = entries
+                .iter()
+                .any(|&(other, other_body)| other < index && other_body == body);
+            let description = format!(
+                "entry #{}: {} {} matches entry #{}, {}",
+                index,
+                method,
+                path,
+                first,
+                if same_body {
+                    "and is only served in rotation with it"
+                } else {
+                    "and is only served to requests with its body"
+                }
+            );
+            duplicates.push((index, description));
+        }
+    }
+    duplicates.sort();
+    report.duplicates = duplicates
+        .into_iter()
+        .map(|(_, description)| description)
+        .collect();
+
+    let options = ServerOptions {
+        log_level: LogLevel::Off,
+        ..Default::default()
+    };
+    // igniting checks for route collisions without binding the port
+    match build_server(har, &options) {
+        Ok(server) => {
+            if let Err(err) = server.ignite().await {
+                report.server_errors.push(err.to_string());
+            }
+        }
+        Err(err) => report.server_errors.push(err.to_string()),
+    }
+
+    report.invalid = invalid.len();
+    report.valid = har.entries.len() - report.invalid;
+    report
+}
@@ -0,0 +1,96 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+use crate::har::Har;
+
+#[derive(Default)]
+pub struct VerifyReport {
+    // dump files whose contents differ from the HAR's response body
+    pub modified: Vec<PathBuf>,
+    // entries with a response body but no file in the dump
+    pub missing: Vec<PathBuf>,
+    // dump files that don't correspond to any entry
+    pub untracked: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.untracked.is_empty()
+    }
+
+    pub fn print(&self) {
+        for (label, paths) in [
+            ("modified", &self.modified),
+            ("missing", &self.missing),
+            ("untracked", &self.untracked),
+        ] {
+            for path in paths {
+                println!("{}: {}", label, path.display());
+            }
+        }
+        println!(
+            "{} modified, {} missing, {} untracked",
+            self.modified.len(),
+            self.missing.len(),
+            self.untracked.len()
+        );
+    }
+}
+
+// files in the dump, skipping the `_unbundled` directories dump writes
+// webpack chunks to and its manifest
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for dir_entry in read_dir(dir)? {
+        let path = dir_entry?.path();
+        if path.is_dir() {
+            let is_unbundled = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with("_unbundled"));
+            if !is_unbundled {
+                collect_files(&path, files)?;
+            }
+        } else if path
+            .file_name()
+            .is_some_and(|name| name != "_manifest.json")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// compares a dump directory against the HAR it was dumped from. note that
+// unless the dump was made with --raw, unminified javascript (and anything
+// reformatted by --pretty-json, --unminify-css or --unminify-html) will show
+// up as modified, as will scripts whose inline source maps were written out
+// to .map files
+pub fn verify(har: &Har, dump_path: &Path) -> Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let mut expected_paths = HashSet::new();
+    for entry in har.entries.iter().filter(|entry| !entry.is_data_uri()) {
+        let path = entry.get_dump_path(dump_path)?;
+        expected_paths.insert(path.clone());
+        let Some(body) = entry.res_body() else {
+            continue;
+        };
+        if !path.is_file() {
+            report.missing.push(path);
+            continue;
+        }
+        let contents = std::fs::read(&path)?;
+        if Sha256::digest(&contents) != Sha256::digest(&body) {
+            report.modified.push(path);
+        }
+    }
+
+    let mut files = Vec::new();
+    collect_files(dump_path, &mut files)?;
+    report.untracked = files
+        .into_iter()
+        .filter(|path| !expected_paths.contains(path))
+        .collect();
+    Ok(report)
+}
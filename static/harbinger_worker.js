@@ -1,5 +1,6 @@
 const HARBINGER_PORT = HARBINGER_TMPL_PORT;
 const HARBINGER_ORIGIN_HOST = "HARBINGER_TMPL_ORIGIN_HOST";
+const HARBINGER_PROTOCOL = "HARBINGER_TMPL_PROTOCOL";
 
 const INTERNAL_PATHS = [
     '/harbinger',
@@ -28,7 +29,7 @@ function rewriteUrl(reqUrlStr) {
     }
     url.hostname = 'localhost';
     url.port = HARBINGER_PORT;
-    url.protocol = 'http';
+    url.protocol = HARBINGER_PROTOCOL;
     return url;
 }
 
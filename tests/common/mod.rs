@@ -0,0 +1,59 @@
+// helpers shared by the integration tests, which each only use some of them
+#![allow(dead_code)]
+
+use harbinger::Har;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+// a recorded GET of `url`, answered with `body`
+pub fn entry(url: &str, content_type: &str, body: &str) -> Value {
+    json!({
+        "startedDateTime": "2023-06-01T12:00:00.000Z",
+        "time": 12.0,
+        "request": {
+            "method": "GET",
+            "url": url,
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": [],
+            "queryString": [],
+            "headersSize": -1,
+            "bodySize": 0
+        },
+        "response": {
+            "status": 200,
+            "statusText": "OK",
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": [{ "name": "Content-Type", "value": content_type }],
+            "content": { "size": body.len(), "mimeType": content_type, "text": body },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": body.len()
+        },
+        "cache": {},
+        "timings": { "send": 0.0, "wait": 12.0, "receive": 0.0 }
+    })
+}
+
+pub fn har(entries: Vec<Value>) -> Har {
+    Har::from_json_value(json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "harbinger tests", "version": "1.0" },
+            "entries": entries
+        }
+    }))
+    .unwrap()
+}
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+pub fn fixture(name: &str) -> String {
+    std::fs::read_to_string(fixture_path(name)).unwrap()
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use harbinger::{dump, DumpOptions};
+
+// {"version":3,"sources":["add.ts"],"names":[],"mappings":"AAAA"}
+const INLINE_MAP: &str = "data:application/json;base64,eyJ2ZXJzaW9uIjozLCJzb3VyY2VzIjpbImFkZC50cyJdLCJuYW1lcyI6W10sIm1hcHBpbmdzIjoiQUFBQSJ9";
+
+#[rocket::async_test]
+async fn unminified_scripts_link_their_inline_maps() {
+    let script = format!(
+        "function add(a,b){{return a+b}}\n//# sourceMappingURL={}\n",
+        INLINE_MAP
+    );
+    let har = common::har(vec![common::entry(
+        "https://cool.website/static/add.js",
+        "application/javascript",
+        &script,
+    )]);
+    let tmp = tempfile::tempdir().unwrap();
+    let out = tmp.path().join("dump");
+    let options = DumpOptions {
+        no_progress: true,
+        ..Default::default()
+    };
+    dump(&har, &out, &options).await.unwrap();
+
+    let static_dir = out.join("GET/cool.website/static");
+    let dumped = std::fs::read_to_string(static_dir.join("add.js")).unwrap();
+    assert!(dumped.contains("return a + b"), "{}", dumped);
+    assert!(!dumped.contains("data:"), "{}", dumped);
+    assert_eq!(
+        dumped.trim_end().lines().last(),
+        Some("//# sourceMappingURL=add.js.map")
+    );
+    let map: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(static_dir.join("add.js.map")).unwrap()).unwrap();
+    assert_eq!(map["sources"][0], "add.ts");
+}
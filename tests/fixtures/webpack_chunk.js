@@ -0,0 +1,14 @@
+(self["webpackChunk_cool_website"] = self["webpackChunk_cool_website"] || []).push([[179], {
+    204: function(e, t, n) {
+        "use strict";
+        var r = n(512);
+        e.exports = function greet(name) {
+            return r.prefix + name;
+        };
+    },
+    512: (e, t, n) => {
+        "use strict";
+        n.r(t);
+        t.prefix = "hello, ";
+    }
+}]);
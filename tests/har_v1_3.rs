@@ -0,0 +1,65 @@
+mod common;
+
+use harbinger::{build_server, Har, ServerOptions};
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use std::collections::HashSet;
+
+// the fixture, read as it would be had it been written as HAR 1.2
+fn as_v1_2() -> Har {
+    let mut value: serde_json::Value = serde_json::from_str(&common::fixture("v1_3.har")).unwrap();
+    value["log"]["version"] = "1.2".into();
+    Har::from_json_value(value).unwrap()
+}
+
+#[test]
+fn reads_v1_3_like_v1_2() {
+    let har = Har::read(&common::fixture_path("v1_3.har")).unwrap();
+    let v1_2 = as_v1_2();
+
+    assert_eq!(har.entries.len(), 3);
+    assert_eq!(har.origin_host().unwrap(), "cool.website");
+    assert_eq!(har.origin_host().unwrap(), v1_2.origin_host().unwrap());
+    assert_eq!(har.primary_url(), v1_2.primary_url());
+    let pages: Vec<&str> = har.pages().iter().map(|page| page.id.as_str()).collect();
+    assert_eq!(pages, ["page_1"]);
+
+    let keys = |har: &Har| -> HashSet<_> { har.entries().unwrap().into_keys().collect() };
+    assert_eq!(keys(&har), keys(&v1_2));
+    for (entry, v1_2_entry) in har.entries.iter().zip(&v1_2.entries) {
+        assert_eq!(entry.url(), v1_2_entry.url());
+        assert_eq!(entry.content_type(), v1_2_entry.content_type());
+        assert_eq!(entry.res_body(), v1_2_entry.res_body());
+    }
+}
+
+#[rocket::async_test]
+async fn serves_v1_3() {
+    let har = Har::read(&common::fixture_path("v1_3.har")).unwrap();
+    let server = build_server(&har, &ServerOptions::default()).unwrap();
+    let client = Client::tracked(server).await.unwrap();
+
+    for (path, content_type, body) in [
+        (
+            "/",
+            "text/html",
+            "<script src=\"/app.js\"></script>hello!\n",
+        ),
+        (
+            "/app.js?v=2",
+            "application/javascript",
+            "console.log(\"hi\");\n",
+        ),
+        (
+            "/fonts.example.net/font.css",
+            "text/css",
+            "body{color:red}\n",
+        ),
+    ] {
+        let response = client.get(path).dispatch().await;
+        assert_eq!(response.status(), Status::Ok, "{}", path);
+        let served_type = response.headers().get_one("content-type").unwrap();
+        assert!(served_type.starts_with(content_type), "{}", served_type);
+        assert_eq!(response.into_string().await.as_deref(), Some(body));
+    }
+}
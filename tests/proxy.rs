@@ -0,0 +1,134 @@
+mod common;
+
+use harbinger::{build_server, ServerOptions};
+use httptest::matchers::{all_of, contains, key, not, request, url_decoded};
+use httptest::responders::status_code;
+use httptest::{Expectation, Server};
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::json;
+
+#[rocket::async_test]
+async fn proxies_unmatched_requests_upstream() {
+    let upstream = Server::run();
+    upstream.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/api/submit"),
+            request::query(url_decoded(contains(("draft", "1")))),
+            request::headers(contains(("content-type", "application/json"))),
+            request::headers(contains(("x-request-id", "abc123"))),
+            request::body(r#"{"name":"harbinger","tags":["a","b"]}"#),
+        ])
+        .respond_with(
+            status_code(201)
+                .insert_header("content-type", "application/json")
+                .insert_header("x-upstream", "mock")
+                .body(r#"{"id":42}"#),
+        ),
+    );
+
+    let har = common::har(vec![common::entry(
+        "https://cool.website/",
+        "text/html",
+        "hello",
+    )]);
+    let options = ServerOptions {
+        proxy: Some(upstream.url_str("/").parse().unwrap()),
+        ..Default::default()
+    };
+    let client = Client::tracked(build_server(&har, &options).unwrap())
+        .await
+        .unwrap();
+
+    let response = client
+        .post("/api/submit?draft=1")
+        .header(ContentType::JSON)
+        .header(Header::new("X-Request-Id", "abc123"))
+        .body(r#"{"name":"harbinger","tags":["a","b"]}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.headers().get_one("x-upstream"), Some("mock"));
+    assert_eq!(
+        response.headers().get_one("content-type"),
+        Some("application/json")
+    );
+    assert_eq!(
+        response.into_string().await.as_deref(),
+        Some(r#"{"id":42}"#)
+    );
+
+    // HAR entries are still served rather than proxied
+    let response = client.get("/").dispatch().await;
+    assert_eq!(response.into_string().await.as_deref(), Some("hello"));
+}
+
+#[rocket::async_test]
+async fn proxies_bodyless_requests() {
+    let upstream = Server::run();
+    upstream.expect(
+        Expectation::matching(all_of![
+            request::method_path("DELETE", "/api/item/7"),
+            request::body(""),
+        ])
+        .respond_with(status_code(204)),
+    );
+
+    let har = common::har(vec![common::entry(
+        "https://cool.website/",
+        "text/html",
+        "hello",
+    )]);
+    let options = ServerOptions {
+        proxy: Some(upstream.url_str("/").parse().unwrap()),
+        ..Default::default()
+    };
+    let client = Client::tracked(build_server(&har, &options).unwrap())
+        .await
+        .unwrap();
+    let response = client.delete("/api/item/7").dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+}
+
+#[rocket::async_test]
+async fn sends_jar_cookies_matching_the_request_path() {
+    let upstream = Server::run();
+    upstream.expect(
+        Expectation::matching(all_of![
+            request::method_path("GET", "/api/me"),
+            request::headers(contains(("cookie", "session=abc"))),
+        ])
+        .respond_with(status_code(200)),
+    );
+    upstream.expect(
+        Expectation::matching(all_of![
+            request::method_path("GET", "/static/logo.png"),
+            request::headers(not(contains(key("cookie")))),
+        ])
+        .respond_with(status_code(200)),
+    );
+
+    let mut login = common::entry("https://cool.website/login", "text/html", "welcome");
+    login["response"]["headers"]
+        .as_array_mut()
+        .unwrap()
+        .push(json!({ "name": "Set-Cookie", "value": "session=abc; Path=/api" }));
+    let har = common::har(vec![login]);
+    let dir = tempfile::tempdir().unwrap();
+    let options = ServerOptions {
+        proxy: Some(upstream.url_str("/").parse().unwrap()),
+        cookie_jar: Some(dir.path().join("cookies.json")),
+        ..Default::default()
+    };
+    // untracked, so that only harbinger's jar sends the cookie
+    let client = Client::untracked(build_server(&har, &options).unwrap())
+        .await
+        .unwrap();
+
+    let response = client.get("/login").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get("/api/me").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get("/static/logo.png").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use harbinger::js::print_script;
+use harbinger::{dump, parse_js, unpack_webpack_chunk_list, write_script, DumpOptions};
+
+const CHUNK_URL: &str = "https://cool.website/static/js/179.chunk.js";
+
+#[test]
+fn unpacks_webpack_chunk_fixture() {
+    let script = parse_js(
+        "webpack_chunk.js".to_string(),
+        common::fixture("webpack_chunk.js"),
+    )
+    .unwrap();
+    let chunks = unpack_webpack_chunk_list(&script).expect("fixture is a chunk list");
+    let labels: Vec<&str> = chunks.iter().map(|chunk| chunk.label.as_str()).collect();
+    assert_eq!(labels, ["204", "512"]);
+
+    let out = tempfile::tempdir().unwrap();
+    for chunk in chunks {
+        let path = out.path().join(format!("{}.js", chunk.label));
+        let script = chunk.into_script();
+        // the minified parameter names are replaced by webpack's own
+        let code = print_script(&script);
+        assert!(code.contains("__webpack_require__"), "{}", code);
+        assert!(code.contains("exports"), "{}", code);
+        write_script(&script, &path).unwrap();
+
+        // whatever was written still has to be valid javascript
+        let written = std::fs::read_to_string(&path).unwrap();
+        parse_js(path.display().to_string(), written).unwrap();
+    }
+}
+
+#[test]
+fn renames_module_exports_and_require() {
+    let script = parse_js(
+        "webpack_chunk.js".to_string(),
+        common::fixture("webpack_chunk.js"),
+    )
+    .unwrap();
+    let chunks = unpack_webpack_chunk_list(&script).unwrap();
+    let code = print_script(&chunks.into_iter().next().unwrap().into_script());
+    assert!(code.contains("__webpack_require__(512)"), "{}", code);
+    assert!(code.contains("module.exports = function greet"), "{}", code);
+}
+
+#[rocket::async_test]
+async fn dump_unbundles_chunks_and_writes_manifest() {
+    let har = common::har(vec![common::entry(
+        CHUNK_URL,
+        "application/javascript",
+        &common::fixture("webpack_chunk.js"),
+    )]);
+    let tmp = tempfile::tempdir().unwrap();
+    let out = tmp.path().join("dump");
+    let options = DumpOptions {
+        no_progress: true,
+        ..Default::default()
+    };
+    let summary = dump(&har, &out, &options).await.unwrap();
+    assert_eq!(summary.written, 1);
+    assert!(summary.failures.is_empty());
+
+    let js_dir = out.join("GET/cool.website/static/js");
+    parse_js(
+        "179.chunk.js".to_string(),
+        std::fs::read_to_string(js_dir.join("179.chunk.js")).unwrap(),
+    )
+    .unwrap();
+    let unbundled = js_dir.join("179.chunk_unbundled");
+    let mut chunk_files: Vec<String> = std::fs::read_dir(&unbundled)
+        .unwrap()
+        .map(|file| file.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    chunk_files.sort();
+    assert_eq!(chunk_files, ["204.js", "512.js"]);
+    let chunk = std::fs::read_to_string(unbundled.join("204.js")).unwrap();
+    assert!(chunk.contains("module.exports"), "{}", chunk);
+
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(out.join("_manifest.json")).unwrap()).unwrap();
+    let manifest = manifest.as_array().unwrap();
+    assert_eq!(manifest.len(), 1);
+    assert_eq!(
+        manifest[0]["file"],
+        "GET/cool.website/static/js/179.chunk.js"
+    );
+    assert_eq!(manifest[0]["url"], CHUNK_URL);
+    assert_eq!(
+        manifest[0]["size"],
+        js_dir.join("179.chunk.js").metadata().unwrap().len()
+    );
+}